@@ -1,9 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
 
-use crate::chunk::Chunk;
-use crate::png::Png;
-use std::str::from_utf8;
+use ping_pong::chunk::Chunk;
+use ping_pong::chunk_type::ChunkType;
+use ping_pong::png::{ParseLimits, Png};
+use std::fs;
+use std::io::{BufReader, Read, Write};
+use std::str::{from_utf8, FromStr};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// EncodeArgs options
 #[derive(StructOpt, Debug)]
@@ -11,8 +16,19 @@ use std::str::from_utf8;
 pub enum PngArgs{
     Encode(EncodeArgs),
     Decode(DecodeArgs),
+    Append(AppendArgs),
     Remove(Remove),
     Print(Print),
+    CheckType(CheckTypeArgs),
+    Shuffle(ShuffleArgs),
+    Validate(ValidateArgs),
+    DumpChunk(DumpChunkArgs),
+    MergeIdat(MergeIdatArgs),
+    EncodeMeta(MetaArgs),
+    CompareMsg(CompareMsgArgs),
+    #[structopt(name = "dump-crc-table", setting = AppSettings::Hidden)]
+    DumpCrcTable(DumpCrcTable),
+    Undo(UndoArgs),
 } 
 
 #[derive(StructOpt, Debug)]
@@ -24,41 +40,177 @@ pub struct EncodeArgs {
     pub file_path: PathBuf,
 
     /// 4 letter, valid PNG chunk type code
-    #[structopt(short, long)]
-    pub chunk_type: String,
+    #[structopt(short, long, required_unless = "chunk-type-from-name")]
+    pub chunk_type: Option<String>,
+
+    /// Derive the chunk type from this label instead
+    /// of picking a code by hand
+    #[structopt(long, conflicts_with = "chunk-type")]
+    pub chunk_type_from_name: Option<String>,
 
     /// The message you want encoded into the
     /// PNG file
-    #[structopt(short, long)]
-    pub message: String,
+    #[structopt(short, long, required_unless_one = &["fill", "order-signal"])]
+    pub message: Option<String>,
+
+    /// Generate the message from a repeated byte instead,
+    /// e.g. to test large payloads
+    #[structopt(long, conflicts_with = "message", requires = "fill-count")]
+    pub fill: Option<u8>,
+
+    /// How many times to repeat the --fill byte
+    #[structopt(long, requires = "fill")]
+    pub fill_count: Option<usize>,
 
     /// Optional output file if you dont want the origin to be overwritten
     #[structopt(short, long)]
     pub output_file: Option<PathBuf>,
+
+    /// Print the size of the encoded PNG without writing it
+    #[structopt(long)]
+    pub estimate_only: bool,
+
+    /// Print sizes as raw byte counts instead of KiB/MiB/GiB
+    #[structopt(long)]
+    pub bytes: bool,
+
+    /// Allow an empty message, producing a zero-length chunk
+    #[structopt(long)]
+    pub allow_empty: bool,
+
+    /// Pad the chunk data to this many bytes so every encoded
+    /// chunk has the same size, decode with --padded
+    #[structopt(long)]
+    pub pad_to: Option<usize>,
+
+    /// Report the byte range the new chunk would add without writing
+    #[structopt(long)]
+    pub show_diff: bool,
+
+    /// Encode these bits, e.g. 10110010, in the order of pairs
+    /// of marker chunks instead of in a message
+    #[structopt(long, conflicts_with_all = &["message", "fill"])]
+    pub order_signal: Option<String>,
+
+    /// Insert the chunk even if it is a second IHDR or IEND
+    #[structopt(long)]
+    pub force: bool,
+
+    /// Uppercase the third letter of the chunk type so its
+    /// reserved bit is valid, e.g. rust becomes ruSt
+    #[structopt(long)]
+    pub fix_reserved_bit: bool,
+
+    /// Record the change in this journal so `undo` can reverse it
+    #[structopt(long)]
+    pub journal: Option<PathBuf>,
 }
 
 impl EncodeArgs {
     // process any call to Encode a message
-    pub fn process_req(&self) -> Png{
-        match Chunk::new_no_state(
-            self.chunk_type.clone(), 
-            self.message.as_bytes().to_vec()) {
-                Ok(chunk) => {
-                    match Png::from_file(self.file_path.clone()) {
-                        Ok(mut png) => {
-                            png.append_chunk(chunk);
-                            return png
-                        }
-                        Err(_) => {
-                            panic!("Failed to read png file, is the file formatted as a png?");
-                        } 
-                    }
-                }
-                Err(_) => {
-                    panic!("Invalid chunk type format. Check the png docs for the proper chunk type formatting");
-                }
+    pub fn process_req(&self) -> Result<Png, String> {
+        let chunk_type = self.chunk_type()?;
+        if !self.fix_reserved_bit && !has_valid_reserved_bit(&chunk_type) {
+            eprintln!(
+                "warning: {} has a lowercase third letter, pass --fix-reserved-bit to uppercase it",
+                chunk_type
+            );
+        }
+        if let Some(bits) = &self.order_signal {
+            return self.encode_order_signal(&chunk_type, bits);
         }
-    } 
+        let message = self.message_bytes()?;
+        if message.is_empty() && !self.allow_empty {
+            return Err(String::from(
+                "Refusing to encode an empty message, pass --allow-empty to write a zero-length chunk",
+            ));
+        }
+        let data = match self.pad_to {
+            Some(size) => pad_payload(&message[..], size)?,
+            None => message,
+        };
+        let chunk = Chunk::new_no_state(chunk_type, data).map_err(|_| {
+            String::from("Invalid chunk type format. Check the png docs for the proper chunk type formatting")
+        })?;
+        let mut png = load_png(&self.file_path)?;
+        if !png.ends_with_iend() {
+            eprintln!("warning: no IEND chunk, appending at the end of the file");
+        }
+        if self.force {
+            png.force_append_chunk(chunk);
+        } else if let Err(msg) = png.append_chunk(chunk) {
+            return Err(format!("{}, pass --force to insert it anyway", msg));
+        }
+        Ok(png)
+    }
+
+    pub fn format_size(&self, size: usize) -> String {
+        if self.bytes {
+            size.to_string()
+        } else {
+            format_bytes(size as u64)
+        }
+    }
+
+    // Compare the file on disk with the encoded result and report
+    // where the inserted chunk lands in the output bytes
+    pub fn diff_report(&self, encoded: &Png) -> Result<String, String> {
+        let original = load_png(&self.file_path)?;
+        let (start, end) = match added_range(&original, encoded) {
+            Some(range) => range,
+            None => return Ok(String::from("No bytes would change")),
+        };
+        let sizes = if self.bytes {
+            format!("{} -> {} bytes", original.byte_len(), encoded.byte_len())
+        } else {
+            format!(
+                "{} -> {}",
+                format_bytes(original.byte_len() as u64),
+                format_bytes(encoded.byte_len() as u64)
+            )
+        };
+        Ok(format!(
+            "bytes [{}..{}] added for new {} chunk ({})",
+            start,
+            end,
+            self.chunk_type()?,
+            sizes
+        ))
+    }
+
+    fn encode_order_signal(&self, chunk_type: &str, bits: &str) -> Result<Png, String> {
+        let bits = parse_bits(bits)?;
+        let chunk_type = ChunkType::from_str(chunk_type).map_err(|_| {
+            String::from("Invalid chunk type format. Check the png docs for the proper chunk type formatting")
+        })?;
+        let mut png = load_png(&self.file_path)?;
+        png.embed_order_signal(&chunk_type, &bits[..])?;
+        Ok(png)
+    }
+
+    fn message_bytes(&self) -> Result<Vec<u8>, String> {
+        match (&self.message, self.fill) {
+            (Some(message), _) => Ok(message.as_bytes().to_vec()),
+            (None, Some(byte)) => Ok(vec![byte; self.fill_count.unwrap_or(0)]),
+            (None, None) => Err(String::from("Either --message or --fill is needed")),
+        }
+    }
+
+    fn chunk_type(&self) -> Result<String, String> {
+        let mut chunk_type = match (&self.chunk_type, &self.chunk_type_from_name) {
+            (Some(chunk_type), _) => normalize_chunk_type(chunk_type)?,
+            (None, Some(label)) => ChunkType::from_label(&label[..]).to_string(),
+            (None, None) => {
+                return Err(String::from("Either --chunk-type or --chunk-type-from-name is needed"))
+            }
+        };
+        if self.fix_reserved_bit {
+            // only the case of the third letter carries the reserved bit,
+            // so the other property bits are left alone
+            chunk_type.get_mut(2..3).unwrap().make_ascii_uppercase();
+        }
+        Ok(chunk_type)
+    }
 }
 
 #[derive(StructOpt, Debug)]
@@ -72,20 +224,370 @@ pub struct DecodeArgs {
     /// that contains the hidden message
     #[structopt(short, long)]
     pub chunk_type: String,
+
+    /// Stop parsing files with more chunks than this
+    #[structopt(long, default_value = "100000")]
+    pub max_chunks: usize,
+
+    /// Reject chunks declaring more data bytes than this
+    #[structopt(long, default_value = "2147483647")]
+    pub max_chunk_size: u32,
+
+    /// Decode even if the file's chunk CRCs don't match their data
+    #[structopt(long)]
+    pub ignore_crc: bool,
+
+    /// Print the chunk data as a hex dump instead of text
+    #[structopt(long)]
+    pub hexdump: bool,
+
+    /// Treat the chunk type as a prefix and join every matching
+    /// chunk in the order of the index at the start of its data
+    #[structopt(long)]
+    pub reassemble: bool,
+
+    /// Trim leading and trailing whitespace from the decoded text
+    #[structopt(long)]
+    pub trim: bool,
+
+    /// The message was encoded with --pad-to, strip the padding
+    #[structopt(long)]
+    pub padded: bool,
+
+    /// Also print the chunk's byte offset and data length to stderr
+    #[structopt(long)]
+    pub with_offset: bool,
+
+    /// Output format, json wraps the chunk data with its metadata
+    /// and can't be combined with --range, --trim, --padded or --hexdump
+    #[structopt(long, default_value = "text", possible_values = &["text", "json"])]
+    pub format: String,
+
+    /// Print the bits hidden in the order of the marker
+    /// chunks written by encode --order-signal
+    #[structopt(long)]
+    pub order_signal: bool,
+
+    /// Also write the decoded message to <image>.<chunk type>.txt
+    /// next to the PNG file
+    #[structopt(long)]
+    pub sidecar: bool,
+
+    /// Only output this byte range of the chunk data, as START..END,
+    /// START.. or ..END
+    #[structopt(long)]
+    pub range: Option<String>,
+
+    /// Decode every chunk of the type, one message per line,
+    /// instead of only the first
+    #[structopt(long)]
+    pub all: bool,
 }
 
 impl DecodeArgs {
-    pub fn process_req(&self) -> String {
-        match Png::from_file(self.file_path.clone()) {
-            Ok(png) => {
-                let chunk = png.chunk_by_type(&self.chunk_type[..]).unwrap();
-                return chunk.data_as_string().unwrap();
+    fn limits(&self) -> ParseLimits {
+        ParseLimits {
+            max_chunks: self.max_chunks,
+            max_chunk_size: self.max_chunk_size,
+        }
+    }
+
+    pub fn process_req(&self) -> Result<String, String> {
+        // json always holds the whole, unmodified chunk data, so
+        // the options that slice or reformat it would be dropped
+        if self.format == "json" && (self.range.is_some() || self.trim || self.padded || self.hexdump) {
+            return Err(String::from(
+                "--format json can't be combined with --range, --trim, --padded or --hexdump",
+            ));
+        }
+        let contents = read_file(&self.file_path)?;
+        let png = if self.ignore_crc {
+            Png::try_from_ignoring_crc(&contents[..], self.limits())
+        } else {
+            Png::try_from_with_limit(&contents[..], self.limits())
+        };
+        let png = png.map_err(|msg| format!("Failed to load png from file: {}", msg))?;
+        if self.order_signal {
+            let bits = png.read_order_signal(&self.chunk_type[..])?;
+            return Ok(bits.iter().map(|&bit| if bit { '1' } else { '0' }).collect());
+        }
+        if self.reassemble {
+            let data = png.reassemble(&self.chunk_type[..])?;
+            return self.render(&data[..]);
+        }
+        let chunks = png.chunks_by_type(&self.chunk_type[..]);
+        if chunks.is_empty() {
+            return Err(format!("No {} chunk in the PNG file", self.chunk_type));
+        }
+        let count = if self.all { chunks.len() } else { 1 };
+        let indices = png.find_all_indices(&self.chunk_type[..]);
+        let mut messages = Vec::new();
+        for (chunk, index) in chunks.into_iter().zip(indices).take(count) {
+            if self.with_offset {
+                eprintln!("offset: {} length: {}", png.chunk_offset(index).unwrap(), chunk.length());
             }
-            Err(_) => {
-                panic!("Failed to load png from file");
+            messages.push(self.decode_chunk(chunk)?);
+        }
+        Ok(messages.join("\n"))
+    }
+
+    fn decode_chunk(&self, chunk: &Chunk) -> Result<String, String> {
+        if !chunk.crc_matches() {
+            eprintln!("warning: {} chunk CRC does not match its data", chunk.chunk_type());
+        }
+        if self.format == "json" {
+            return Ok(chunk_json(chunk));
+        }
+        let data = if self.padded {
+            unpad_payload(chunk.data())?
+        } else {
+            chunk.data()
+        };
+        self.render(self.slice(data)?)
+    }
+
+    fn slice<'a>(&self, data: &'a [u8]) -> Result<&'a [u8], String> {
+        match &self.range {
+            Some(spec) => {
+                let (start, end) = parse_range(spec, data.len())?;
+                Ok(&data[start..end])
+            }
+            None => Ok(data),
+        }
+    }
+
+    pub fn sidecar_path(&self) -> PathBuf {
+        let mut name = self.file_path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".{}.txt", self.chunk_type));
+        self.file_path.with_file_name(name)
+    }
+
+    pub fn write_sidecar(&self, message: &str) -> Result<(), String> {
+        let path = self.sidecar_path();
+        fs::write(&path, message)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+    }
+
+    fn render(&self, data: &[u8]) -> Result<String, String> {
+        if self.hexdump {
+            return Ok(hexdump(data));
+        }
+        let text = String::from_utf8(data.to_vec())
+            .map_err(|_| String::from("Chunk data is not valid UTF-8, decode it with --hexdump"))?;
+        if self.trim {
+            return Ok(text.trim().to_string());
+        }
+        Ok(text)
+    }
+}
+
+// Parse START..END, START.. or ..END into bounds within `len`
+fn parse_range(spec: &str, len: usize) -> Result<(usize, usize), String> {
+    let invalid = || format!("Invalid range {:?}, expected START..END", spec);
+    let split = spec.find("..").ok_or_else(invalid)?;
+    let bound = |value: &str, default: usize| {
+        if value.is_empty() {
+            Ok(default)
+        } else {
+            value.parse::<usize>().map_err(|_| invalid())
+        }
+    };
+    let start = bound(&spec[..split], 0)?;
+    let end = bound(&spec[split + 2..], len)?;
+    if start > end || end > len {
+        return Err(format!(
+            "Range {}..{} is out of bounds for {} bytes of chunk data",
+            start, end, len
+        ));
+    }
+    Ok((start, end))
+}
+
+fn has_valid_reserved_bit(chunk_type: &str) -> bool {
+    match ChunkType::from_str(chunk_type) {
+        Ok(chunk_type) => chunk_type.is_reserved_bit_valid(),
+        Err(_) => true,
+    }
+}
+
+// Render a byte count with a binary unit, e.g. 1536 as 1.50 KiB
+pub fn format_bytes(n: u64) -> String {
+    const UNITS: [&str; 3] = ["KiB", "MiB", "GiB"];
+    if n < 1024 {
+        return format!("{} B", n);
+    }
+    let mut size = n as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, UNITS[unit])
+}
+
+// Trim a chunk type given on the command line and make sure what
+// is left is exactly 4 ASCII letters, naming any offending character
+fn normalize_chunk_type(input: &str) -> Result<String, String> {
+    let chunk_type = input.trim();
+    let count = chunk_type.chars().count();
+    if count != 4 {
+        return Err(format!(
+            "Invalid chunk type {:?}: expected 4 ASCII letters, got {} characters",
+            input, count
+        ));
+    }
+    let offending: Vec<String> = chunk_type
+        .chars()
+        .enumerate()
+        .filter(|(_, c)| !c.is_ascii_alphabetic())
+        .map(|(i, c)| format!("{:?} at position {}", c, i + 1))
+        .collect();
+    if !offending.is_empty() {
+        return Err(format!(
+            "Invalid chunk type {:?}: not an ASCII letter: {}",
+            input,
+            offending.join(", ")
+        ));
+    }
+    Ok(chunk_type.to_string())
+}
+
+// Prefix the message with its length and fill the rest with
+// zeros so the chunk data is exactly `size` bytes
+fn pad_payload(message: &[u8], size: usize) -> Result<Vec<u8>, String> {
+    if message.len() + 4 > size {
+        return Err(format!(
+            "Message needs {} bytes with its length prefix but --pad-to is {}",
+            message.len() + 4,
+            size
+        ));
+    }
+    let mut data = (message.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(message);
+    data.resize(size, 0);
+    Ok(data)
+}
+
+fn parse_bits(bits: &str) -> Result<Vec<bool>, String> {
+    if bits.is_empty() {
+        return Err(String::from("--order-signal needs at least one bit"));
+    }
+    bits.chars()
+        .map(|bit| match bit {
+            '0' => Ok(false),
+            '1' => Ok(true),
+            other => Err(format!("Invalid bit {:?} in --order-signal, use only 0 and 1", other)),
+        })
+        .collect()
+}
+
+// Byte range in `after` taken by the first chunk that is not in
+// `before`, which is where a single insertion went
+fn added_range(before: &Png, after: &Png) -> Option<(usize, usize)> {
+    let index = after
+        .chunks()
+        .iter()
+        .zip(before.chunks().iter().map(Some).chain(std::iter::repeat(None)))
+        .position(|(new, old)| old != Some(new))?;
+    let start = after.chunk_offset(index)?;
+    Some((start, start + 12 + after.chunks()[index].length() as usize))
+}
+
+fn unpad_payload(data: &[u8]) -> Result<&[u8], &'static str> {
+    if data.len() < 4 {
+        return Err("Padded chunk is too short to hold a length prefix");
+    }
+    let length = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+    if length > data.len() - 4 {
+        return Err("Padded chunk's length prefix exceeds its data");
+    }
+    Ok(&data[4..4 + length])
+}
+
+// The chunk's metadata and data as a JSON object, `text` is
+// only filled in when the data is valid UTF-8
+fn chunk_json(chunk: &Chunk) -> String {
+    let text = if chunk.is_text() {
+        json_string(&chunk.text_lossy())
+    } else {
+        String::from("null")
+    };
+    format!(
+        "{{\"chunk_type\":{},\"length\":{},\"crc\":{},\"data_base64\":{},\"text\":{}}}",
+        json_string(&chunk.chunk_type().to_string()),
+        chunk.length(),
+        chunk.crc(),
+        json_string(&base64_encode(chunk.data())),
+        text
+    )
+}
+
+fn json_string(value: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+// Standard base64 with padding
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::new();
+    for group in data.chunks(3) {
+        let bytes = [group[0], *group.get(1).unwrap_or(&0), *group.get(2).unwrap_or(&0)];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= group.len() {
+                encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
             }
         }
     }
+    encoded
+}
+
+#[derive(StructOpt, Debug)]
+/// Append more message data to an existing chunk
+pub struct AppendArgs {
+    /// The PNG with the chunk to extend
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+
+    /// 4 letter PNG chunk type code of the chunk to extend
+    #[structopt(short, long)]
+    pub chunk_type: String,
+
+    /// The message to add to the end of the chunk's data
+    #[structopt(short, long)]
+    pub message: String,
+
+    /// Optional output file if you dont want the origin to be overwritten
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    /// Record the change in this journal so `undo` can reverse it
+    #[structopt(long)]
+    pub journal: Option<PathBuf>,
+}
+
+impl AppendArgs {
+    pub fn process_req(&self) -> Result<Png, String> {
+        let mut png = load_png(&self.file_path)?;
+        png.append_to_chunk(&self.chunk_type[..], self.message.as_bytes())
+            .map_err(|_| format!("No {} chunk in the PNG file", self.chunk_type))?;
+        Ok(png)
+    }
 }
 
 #[derive(StructOpt, Debug)]
@@ -95,16 +597,35 @@ pub struct Remove {
     #[structopt(short, long)]
     pub file_path: PathBuf,
 
-    /// The chunk type containing the cnoded message
+    /// The chunk type containing the cnoded message, or a glob
+    /// like ??Xt or te* matching several ancillary types
     #[structopt(short, long)]
     pub chunk_type: String,
+
+    /// Optional output file if you dont want the origin to be overwritten
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+
+    /// Record the change in this journal so `undo` can reverse it
+    #[structopt(long)]
+    pub journal: Option<PathBuf>,
 }
 
 impl Remove {
-    pub fn process_req(&self) -> bool {
-        let mut png: Png = Png::from_file(self.file_path.clone()).unwrap();
-        png.remove_chunk(&self.chunk_type[..]).unwrap();
-        return true
+    pub fn process_req(&self) -> Result<Png, String> {
+        let mut png = load_png(&self.file_path)?;
+        let missing = format!("No {} chunk in the PNG file", self.chunk_type);
+        if self.chunk_type.contains(['*', '?']) {
+            if png.remove_matching(&self.chunk_type[..]).is_empty() {
+                return Err(missing);
+            }
+        } else {
+            if !png.contains_chunk_type(&self.chunk_type[..]) {
+                return Err(missing);
+            }
+            png.remove_chunk(&self.chunk_type[..])?;
+        }
+        Ok(png)
     }
 }
 
@@ -112,22 +633,1512 @@ impl Remove {
 #[derive(StructOpt, Debug)]
 /// Print the encoded message in the PNG file
 pub struct Print {
-    /// The PNG file containing the encoded message
-    #[structopt(short, long)]
-    pub file_path: PathBuf,
+    /// The PNG files containing the encoded messages
+    #[structopt(short, long, required = true)]
+    pub file_path: Vec<PathBuf>,
+
+    /// Fail on the first chunk that is not valid UTF-8
+    /// instead of skipping it
+    #[structopt(long)]
+    pub strict_text: bool,
+
+    /// Stop parsing files with more chunks than this
+    #[structopt(long, default_value = "100000")]
+    pub max_chunks: usize,
+
+    /// Reject chunks declaring more data bytes than this
+    #[structopt(long, default_value = "2147483647")]
+    pub max_chunk_size: u32,
+
+    /// Print every chunk's data as a hex dump, binary chunks included
+    #[structopt(long)]
+    pub hexdump: bool,
+
+    /// Print sizes as raw byte counts instead of KiB/MiB/GiB
+    #[structopt(long)]
+    pub bytes: bool,
 }
 
 
 impl Print {
-    pub fn process_req(&self) {
-        let png: Png = Png::from_file(self.file_path.clone()).unwrap();
+    pub fn process_req(&self) -> Result<(), String> {
+        print!("{}", self.render()?);
+        Ok(())
+    }
+
+    // Output for every file in turn, each under a header
+    // line when more than one file is given
+    fn render(&self) -> Result<String, String> {
+        let mut output = String::new();
+        for path in self.file_path.iter() {
+            if self.file_path.len() > 1 {
+                output.push_str(&format!("==> {} <==\n", path.display()));
+            }
+            let contents = read_file(path)?;
+            let png: Png = Png::try_from_with_limit(
+                &contents[..],
+                ParseLimits {
+                    max_chunks: self.max_chunks,
+                    max_chunk_size: self.max_chunk_size,
+                },
+            )
+            .map_err(|msg| format!("{}: {}", path.display(), msg))?;
+            if self.hexdump {
+                for chunk in png.chunks().iter() {
+                    let size = if self.bytes {
+                        format!("{} bytes", chunk.length())
+                    } else {
+                        format_bytes(chunk.length() as u64)
+                    };
+                    output.push_str(&format!("{} ({})\n", chunk.chunk_type(), size));
+                    output.push_str(&format!("{}\n", hexdump(chunk.data())));
+                }
+                continue;
+            }
+            for fstr in self.text_chunks(&png)? {
+                output.push_str(&format!("{}\n", fstr));
+            }
+        }
+        Ok(output)
+    }
+
+    // Binary chunks are skipped unless `strict_text` is set,
+    // in which case the first one is reported as an error
+    fn text_chunks<'a>(&self, png: &'a Png) -> Result<Vec<&'a str>, String> {
+        let mut texts = Vec::new();
         for chunk in png.chunks().iter() {
             match from_utf8(chunk.data()) {
-                Ok(fstr) => {
-                    println!("{}", fstr);
+                Ok(fstr) => texts.push(fstr),
+                Err(_) if self.strict_text => {
+                    return Err(format!("{} chunk data is not valid UTF-8", chunk.chunk_type()));
                 }
                 Err(_) => {}
             }
         }
+        Ok(texts)
+    }
+}
+
+// Read a file, naming it in the error when it can't be read
+fn read_file(path: &Path) -> Result<Vec<u8>, String> {
+    fs::read(path).map_err(|err| format!("Failed to read {}: {}", path.display(), err))
+}
+
+fn load_png(path: &Path) -> Result<Png, String> {
+    let contents = read_file(path)?;
+    Png::try_from(&contents[..]).map_err(|msg| format!("{}: {}", path.display(), msg))
+}
+
+// Write out the PNG, naming the target in the error so a
+// read-only file or missing directory is easy to spot
+pub fn write_png(path: &Path, png: &Png) -> Result<(), String> {
+    write_bytes(path, &png.as_bytes()[..])
+}
+
+pub fn write_bytes(path: &Path, bytes: &[u8]) -> Result<(), String> {
+    fs::write(path, bytes).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+// Render bytes in 16 byte rows of offset, hex and an ASCII
+// gutter where non-printable bytes show up as '.'
+pub fn hexdump(data: &[u8]) -> String {
+    let mut rows = Vec::new();
+    for (row, bytes) in data.chunks(16).enumerate() {
+        let mut hex = String::new();
+        for (i, byte) in bytes.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{:02x} ", byte));
+        }
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        rows.push(format!("{:08x}  {:<49} |{}|", row * 16, hex, ascii));
+    }
+    rows.join("\n")
+}
+
+#[derive(StructOpt, Debug)]
+/// Check whether a chunk type code is suitable for a hidden message
+pub struct CheckTypeArgs {
+    /// 4 letter PNG chunk type code to check
+    #[structopt(short, long)]
+    pub chunk_type: String,
+}
+
+impl CheckTypeArgs {
+    // returns false when the chunk type is not valid
+    pub fn process_req(&self) -> bool {
+        match ChunkType::from_str(&self.chunk_type[..]) {
+            Ok(chunk_type) => {
+                print!("{}", describe_chunk_type(&chunk_type));
+                chunk_type.is_valid()
+            }
+            Err(msg) => {
+                eprintln!("{}", msg);
+                false
+            }
+        }
+    }
+}
+
+fn describe_chunk_type(chunk_type: &ChunkType) -> String {
+    let mut report = format!(
+        "{}\n  valid: {}\n  critical: {}\n  public: {}\n  safe to copy: {}\n  reserved bit valid: {}\n",
+        chunk_type,
+        chunk_type.is_valid(),
+        chunk_type.is_critical(),
+        chunk_type.is_public(),
+        chunk_type.is_safe_to_copy(),
+        chunk_type.is_reserved_bit_valid()
+    );
+    if !chunk_type.is_valid() {
+        report.push_str("error: the third letter must be uppercase\n");
+    } else if chunk_type.is_critical() {
+        report.push_str("warning: critical chunks must be understood by every decoder, use a lowercase first letter\n");
+    } else if chunk_type.is_public() {
+        report.push_str("warning: public chunk types are reserved for the PNG spec, use a lowercase second letter\n");
+    } else if !chunk_type.is_safe_to_copy() {
+        report.push_str("warning: editors will drop this chunk when modifying the image, use a lowercase fourth letter\n");
+    } else {
+        report.push_str("recommended for hiding messages\n");
+    }
+    report
+}
+
+#[derive(StructOpt, Debug)]
+/// Compare the messages hidden in two PNG files, exiting non-zero if they differ
+pub struct CompareMsgArgs {
+    /// The first PNG file
+    pub first: PathBuf,
+
+    /// The second PNG file
+    pub second: PathBuf,
+
+    /// 4 letter chunk type code holding the messages
+    #[structopt(short, long)]
+    pub chunk_type: String,
+}
+
+impl CompareMsgArgs {
+    // returns false when the messages differ or can't be read
+    pub fn process_req(&self) -> bool {
+        match self.diff() {
+            Ok(None) => true,
+            Ok(Some(diff)) => {
+                println!("{}", diff);
+                false
+            }
+            Err(msg) => {
+                eprintln!("{}", msg);
+                false
+            }
+        }
+    }
+
+    // None when both messages are byte-equal
+    pub fn diff(&self) -> Result<Option<String>, String> {
+        let first = self.message(&self.first)?;
+        let second = self.message(&self.second)?;
+        if first == second {
+            return Ok(None);
+        }
+        let offset = first
+            .iter()
+            .zip(second.iter())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| first.len().min(second.len()));
+        Ok(Some(format!(
+            "messages differ at byte {}\n< {}\n> {}",
+            offset,
+            String::from_utf8_lossy(&first[..]),
+            String::from_utf8_lossy(&second[..])
+        )))
+    }
+
+    fn message(&self, path: &Path) -> Result<Vec<u8>, String> {
+        let png = load_png(path)?;
+        match png.chunk_by_type(&self.chunk_type[..]) {
+            Some(chunk) => Ok(chunk.data().to_vec()),
+            None => Err(format!("No {} chunk in {}", self.chunk_type, path.display())),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Add a tEXt chunk for each keyword=value line of a file
+pub struct MetaArgs {
+    /// The PNG file to add the metadata to
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+
+    /// File of keyword=value lines, blank lines are skipped
+    #[structopt(short, long)]
+    pub meta_file: PathBuf,
+
+    /// Optional output file if you dont want the origin to be overwritten
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+}
+
+impl MetaArgs {
+    pub fn process_req(&self) -> Result<Png, String> {
+        let mut png = load_png(&self.file_path)?;
+        let meta = String::from_utf8(read_file(&self.meta_file)?)
+            .map_err(|_| format!("{} is not valid UTF-8", self.meta_file.display()))?;
+        for (number, line) in meta.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let (keyword, value) = match line.find('=') {
+                Some(split) => (&line[..split], &line[split + 1..]),
+                None => return Err(format!("Line {} is not a keyword=value pair", number + 1)),
+            };
+            png.insert_text(keyword, value)
+                .map_err(|msg| format!("Line {}: {}", number + 1, msg))?;
+        }
+        Ok(png)
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Join all IDAT chunks of a PNG file into a single IDAT
+pub struct MergeIdatArgs {
+    /// The PNG file to normalize
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+
+    /// Optional output file if you dont want the origin to be overwritten
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+}
+
+impl MergeIdatArgs {
+    pub fn process_req(&self) -> Result<Png, String> {
+        let mut png = load_png(&self.file_path)?;
+        png.merge_idat()?;
+        Ok(png)
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Randomly reorder the ancillary chunks of a PNG file
+pub struct ShuffleArgs {
+    /// The PNG file to shuffle
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+
+    /// Seed for a reproducible order, random when omitted
+    #[structopt(short, long)]
+    pub seed: Option<u64>,
+
+    /// Optional output file if you dont want the origin to be overwritten
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+}
+
+impl ShuffleArgs {
+    pub fn process_req(&self) -> Result<Png, String> {
+        let mut png = load_png(&self.file_path)?;
+        let seed = self.seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64
+        });
+        png.shuffle_ancillary(seed);
+        Ok(png)
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Check that PNG files are structurally valid
+pub struct ValidateArgs {
+    /// The PNG files to check
+    #[structopt(short, long, required = true)]
+    pub file_path: Vec<PathBuf>,
+
+    /// Report every problem in a file instead of stopping at the first
+    #[structopt(long)]
+    pub all_errors: bool,
+
+    /// Only check each file's signature, without reading its chunks
+    #[structopt(long, conflicts_with = "all-errors")]
+    pub sniff: bool,
+}
+
+impl ValidateArgs {
+    // returns false when any of the files is invalid
+    pub fn process_req(&self) -> bool {
+        let mut all_valid = true;
+        for path in self.file_path.iter() {
+            if self.sniff {
+                let is_png = Png::sniff(path);
+                println!("{}: {}", path.display(), if is_png { "ok" } else { "not a PNG file" });
+                all_valid &= is_png;
+                continue;
+            }
+            let errors = match fs::File::open(path) {
+                Ok(file) => self.validate(BufReader::new(file)),
+                Err(err) => vec![err.to_string()],
+            };
+            if errors.is_empty() {
+                println!("{}: ok", path.display());
+            } else {
+                all_valid = false;
+                for error in errors.iter() {
+                    println!("{}: {}", path.display(), error);
+                }
+            }
+        }
+        all_valid
+    }
+
+    // Checks the signature, that every chunk's CRC matches and
+    // that the file starts with IHDR and ends with IEND, reading
+    // one chunk at a time so the first problem ends the walk
+    // unless --all-errors is set
+    fn validate<R: Read>(&self, mut reader: R) -> Vec<String> {
+        let mut errors = Vec::new();
+        let mut signature = [0u8; 8];
+        if reader.read_exact(&mut signature).is_err() || signature != Png::STANDARD_HEADER {
+            return vec![String::from("Invalid PNG file format")];
+        }
+        let mut last_type = None;
+        loop {
+            let mut header = [0u8; 8];
+            match reader.read(&mut header[..1]) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => return vec![err.to_string()],
+            }
+            if reader.read_exact(&mut header[1..]).is_err() {
+                errors.push(String::from("Truncated chunk header"));
+                return errors;
+            }
+            let len = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+            let chunk_type = String::from_utf8_lossy(&header[4..8]).to_string();
+            // type and data feed the CRC, the stored CRC follows them
+            let mut body = header[4..8].to_vec();
+            match reader.by_ref().take(len + 4).read_to_end(&mut body) {
+                Ok(read) if read as u64 == len + 4 => {}
+                _ => {
+                    errors.push(String::from("chunk length exceeds available data"));
+                    return errors;
+                }
+            }
+            let stored = u32::from_be_bytes(body[body.len() - 4..].try_into().unwrap());
+            body.truncate(body.len() - 4);
+            if last_type.is_none() && chunk_type != "IHDR" {
+                errors.push(String::from("first chunk is not IHDR"));
+                if !self.all_errors {
+                    return errors;
+                }
+            }
+            if crc32fast::hash(&body[..]) != stored {
+                errors.push(format!("{} chunk CRC mismatch", chunk_type));
+                if !self.all_errors {
+                    return errors;
+                }
+            }
+            let is_iend = chunk_type == "IEND";
+            last_type = Some(chunk_type);
+            if is_iend {
+                break;
+            }
+        }
+        if last_type.as_deref() != Some("IEND") {
+            errors.push(String::from("last chunk is not IEND"));
+        }
+        errors
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Write a single chunk's raw bytes, including its header and CRC
+pub struct DumpChunkArgs {
+    /// The PNG file containing the chunk
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+
+    /// Position of the chunk in the file, starting at 0
+    #[structopt(short, long, required_unless = "chunk-type")]
+    pub index: Option<usize>,
+
+    /// Dump the first chunk of this type instead of picking by index
+    #[structopt(short, long, conflicts_with = "index")]
+    pub chunk_type: Option<String>,
+
+    /// Optional output file, the bytes go to stdout otherwise
+    #[structopt(short, long)]
+    pub output_file: Option<PathBuf>,
+}
+
+impl DumpChunkArgs {
+    pub fn process_req(&self) -> Result<Vec<u8>, String> {
+        let png: Png = load_png(&self.file_path)?;
+        let index = match (&self.chunk_type, self.index) {
+            (Some(chunk_type), _) => png
+                .find_chunk_index(&chunk_type[..])
+                .ok_or(format!("No {} chunk in the PNG file", chunk_type))?,
+            (None, Some(index)) => index,
+            (None, None) => return Err(String::from("Either --index or --chunk-type is needed")),
+        };
+        match png.chunks().get(index) {
+            Some(chunk) => Ok(chunk.as_bytes()),
+            None => Err(format!("The PNG file has only {} chunks", png.chunks().len())),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+/// Print each chunk's stored CRC next to the recomputed one
+pub struct DumpCrcTable {
+    /// The PNG file to inspect
+    #[structopt(short, long)]
+    pub file_path: PathBuf,
+}
+
+impl DumpCrcTable {
+    pub fn process_req(&self) -> Result<(), String> {
+        let contents = read_file(&self.file_path)?;
+        let rows = Png::crc_table(&contents[..])?;
+        print!("{}", format_crc_table(&rows));
+        Ok(())
+    }
+}
+
+// One line per chunk, flagging the rows where the stored
+// and recomputed CRCs disagree
+fn format_crc_table(rows: &[(ChunkType, u32, u32)]) -> String {
+    let mut table = format!("{:<6}{:>12}{:>12}\n", "TYPE", "STORED", "COMPUTED");
+    for (chunk_type, stored, computed) in rows.iter() {
+        table.push_str(&format!(
+            "{:<6}{:>12}{:>12}{}\n",
+            chunk_type.to_string(),
+            format!("{:08x}", stored),
+            format!("{:08x}", computed),
+            if stored != computed { "  MISMATCH" } else { "" }
+        ));
+    }
+    table
+}
+
+#[derive(StructOpt, Debug)]
+/// Reverse the last change recorded in a journal
+pub struct UndoArgs {
+    /// The journal written by --journal
+    #[structopt(short, long)]
+    pub journal: PathBuf,
+}
+
+impl UndoArgs {
+    // Reverse the newest entry, then drop it from the journal
+    // so the next undo steps further back
+    pub fn process_req(&self) -> Result<(), String> {
+        let contents = fs::read_to_string(&self.journal)
+            .map_err(|err| format!("Failed to read {}: {}", self.journal.display(), err))?;
+        let mut lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        let last = lines
+            .pop()
+            .ok_or_else(|| format!("{} has no changes to undo", self.journal.display()))?;
+        let entry = JournalEntry::parse(last)?;
+        let png = entry.reverse(&load_png(&entry.path)?)?;
+        write_png(&entry.path, &png)?;
+        let rest: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+        write_bytes(&self.journal, rest.as_bytes())
+    }
+}
+
+// One change to the file at `path`: the chunks from `start` on
+// that were `removed`, and the type and CRC of each chunk
+// `inserted` in their place
+#[derive(Debug, PartialEq)]
+pub struct JournalEntry {
+    pub path: PathBuf,
+    pub start: usize,
+    pub removed: Vec<Chunk>,
+    pub inserted: Vec<(String, u32)>,
+}
+
+impl JournalEntry {
+    // The span of chunks that differs between `before` and `after`,
+    // or None when nothing changed
+    pub fn between(path: &Path, before: &Png, after: &Png) -> Option<JournalEntry> {
+        let (old, new) = (before.chunks(), after.chunks());
+        let start = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let common = old.len().min(new.len()) - start;
+        let end = old
+            .iter()
+            .rev()
+            .zip(new.iter().rev())
+            .take(common)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if start + end == old.len() && start + end == new.len() {
+            return None;
+        }
+        Some(JournalEntry {
+            path: path.to_path_buf(),
+            start,
+            removed: old[start..old.len() - end].to_vec(),
+            inserted: new[start..new.len() - end]
+                .iter()
+                .map(|chunk| (chunk.chunk_type().to_string(), chunk.crc()))
+                .collect(),
+        })
+    }
+
+    // START, removed chunks in hex, inserted TYPE:CRC pairs and the
+    // path, tab separated so the path is free to hold commas
+    fn to_line(&self) -> String {
+        let removed: Vec<String> = self.removed.iter().map(|chunk| hex(&chunk.as_bytes())).collect();
+        let inserted: Vec<String> = self
+            .inserted
+            .iter()
+            .map(|(chunk_type, crc)| format!("{}:{:08x}", chunk_type, crc))
+            .collect();
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.start,
+            removed.join(","),
+            inserted.join(","),
+            self.path.display()
+        )
+    }
+
+    fn parse(line: &str) -> Result<JournalEntry, String> {
+        let invalid = || format!("Invalid journal entry {:?}", line);
+        let fields: Vec<&str> = line.splitn(4, '\t').collect();
+        if fields.len() != 4 {
+            return Err(invalid());
+        }
+        let start = fields[0].parse::<usize>().map_err(|_| invalid())?;
+        let removed = fields[1]
+            .split(',')
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let bytes = unhex(field).ok_or_else(invalid)?;
+                Chunk::try_from(&bytes[..]).map_err(|_| invalid())
+            })
+            .collect::<Result<Vec<Chunk>, String>>()?;
+        let inserted = fields[2]
+            .split(',')
+            .filter(|field| !field.is_empty())
+            .map(|field| {
+                let (chunk_type, crc) = field.split_once(':').ok_or_else(invalid)?;
+                let crc = u32::from_str_radix(crc, 16).map_err(|_| invalid())?;
+                Ok((chunk_type.to_string(), crc))
+            })
+            .collect::<Result<Vec<(String, u32)>, String>>()?;
+        Ok(JournalEntry {
+            path: PathBuf::from(fields[3]),
+            start,
+            removed,
+            inserted,
+        })
+    }
+
+    // Put the removed chunks back, refusing when the inserted ones
+    // are no longer where the entry says they are
+    fn reverse(&self, png: &Png) -> Result<Png, String> {
+        let end = self.start + self.inserted.len();
+        let unchanged = match png.chunks().get(self.start..end) {
+            Some(current) => current.iter().zip(self.inserted.iter()).all(|(chunk, (chunk_type, crc))| {
+                chunk.chunk_type().to_string() == *chunk_type && chunk.crc() == *crc
+            }),
+            None => false,
+        };
+        if !unchanged {
+            return Err(format!(
+                "{} changed since the journaled edit, not undoing it",
+                self.path.display()
+            ));
+        }
+        let mut chunks = png.chunks().to_vec();
+        chunks.splice(self.start..end, self.removed.iter().cloned());
+        Ok(Png::from_chunks(chunks))
+    }
+}
+
+// Write `png` to `target` like `write_png`, first recording in
+// `journal` how it differs from the PNG at `source`
+pub fn write_journaled(
+    source: &Path,
+    target: &Path,
+    png: &Png,
+    journal: Option<&PathBuf>,
+) -> Result<(), String> {
+    let journal = match journal {
+        Some(journal) => journal,
+        None => return write_png(target, png),
+    };
+    let before = load_png(source)?;
+    write_png(target, png)?;
+    // absolute, so undo finds the file from any directory
+    let target = fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf());
+    match JournalEntry::between(&target, &before, png) {
+        Some(entry) => append_journal(journal, &entry),
+        None => Ok(()),
+    }
+}
+
+fn append_journal(journal: &Path, entry: &JournalEntry) -> Result<(), String> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal)
+        .map_err(|err| format!("Failed to open {}: {}", journal.display(), err))?;
+    writeln!(file, "{}", entry.to_line())
+        .map_err(|err| format!("Failed to write {}: {}", journal.display(), err))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 == 1 || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    // Serialized chunk whose stored CRC is overwritten with `crc`
+    fn with_crc(chunk: Chunk, crc: u32) -> Vec<u8> {
+        let mut bytes = chunk.as_bytes();
+        let crc_start = bytes.len() - 4;
+        bytes[crc_start..].copy_from_slice(&crc.to_be_bytes());
+        bytes
+    }
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.bytes().collect())
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("ping_pong_{}_{}", std::process::id(), name))
+    }
+
+    fn encode_args(file_path: PathBuf, chunk_type: &str, message: &str) -> EncodeArgs {
+        EncodeArgs {
+            file_path,
+            chunk_type: Some(String::from(chunk_type)),
+            chunk_type_from_name: None,
+            message: Some(String::from(message)),
+            fill: None,
+            fill_count: None,
+            output_file: None,
+            estimate_only: false,
+            bytes: false,
+            allow_empty: false,
+            pad_to: None,
+            show_diff: false,
+            order_signal: None,
+            force: false,
+            fix_reserved_bit: false,
+            journal: None,
+        }
+    }
+
+    fn decode_args(file_path: PathBuf, chunk_type: &str) -> DecodeArgs {
+        DecodeArgs {
+            file_path,
+            chunk_type: String::from(chunk_type),
+            max_chunks: Png::DEFAULT_MAX_CHUNKS,
+            max_chunk_size: Png::MAX_CHUNK_SIZE,
+            ignore_crc: false,
+            hexdump: false,
+            reassemble: false,
+            trim: false,
+            padded: false,
+            with_offset: false,
+            format: String::from("text"),
+            order_signal: false,
+            sidecar: false,
+            range: None,
+            all: false,
+        }
+    }
+
+    fn testing_png_file(name: &str) -> PathBuf {
+        let path = temp_path(name);
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("IEND", ""),
+        ]);
+        fs::write(&path, png.as_bytes()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_crc_table_shows_mismatch() {
+        let good = chunk_from_strings("ruSt", "fine").as_bytes();
+        let bad = with_crc(chunk_from_strings("teSt", "broken"), 1234);
+
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(good.iter())
+            .chain(bad.iter())
+            .copied()
+            .collect();
+
+        let table = format_crc_table(&Png::crc_table(&bytes[..]).unwrap());
+        let lines: Vec<&str> = table.lines().collect();
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("ruSt") && !lines[1].contains("MISMATCH"));
+        assert!(lines[2].starts_with("teSt") && lines[2].contains("MISMATCH"));
+    }
+
+    #[test]
+    fn test_check_type_recommended() {
+        let report = describe_chunk_type(&ChunkType::from_str("ruSt").unwrap());
+        assert!(report.contains("critical: false"));
+        assert!(report.contains("safe to copy: true"));
+        assert!(report.ends_with("recommended for hiding messages\n"));
+    }
+
+    #[test]
+    fn test_check_type_critical_warning() {
+        let check = CheckTypeArgs {
+            chunk_type: String::from("IHDR"),
+        };
+        let report = describe_chunk_type(&ChunkType::from_str("IHDR").unwrap());
+        assert!(report.contains("critical: true"));
+        assert!(report.contains("warning: critical chunks"));
+        assert!(check.process_req());
+    }
+
+    #[test]
+    fn test_encode_estimate_matches_written_size() {
+        let input = testing_png_file("estimate_in.png");
+        let output = temp_path("estimate_out.png");
+        let mut enc = EncodeArgs {
+            output_file: Some(output.clone()),
+            estimate_only: true,
+            ..encode_args(input.clone(), "ruSt", "hidden")
+        };
+
+        let png = enc.process_req().unwrap();
+        fs::write(&output, png.as_bytes()).unwrap();
+
+        assert_eq!(png.byte_len() as u64, fs::metadata(&output).unwrap().len());
+        assert_eq!(enc.format_size(1536), "1.50 KiB");
+        enc.bytes = true;
+        assert_eq!(enc.format_size(png.byte_len()), png.byte_len().to_string());
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_print_strict_text() {
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("ruSt", "readable"),
+            Chunk::new(ChunkType::from_str("biNy").unwrap(), vec![0xff, 0xfe, 0x00]),
+        ]);
+        let mut prnt = Print {
+            file_path: Vec::new(),
+            strict_text: false,
+            max_chunks: Png::DEFAULT_MAX_CHUNKS,
+            max_chunk_size: Png::MAX_CHUNK_SIZE,
+            hexdump: false,
+            bytes: false,
+        };
+        assert_eq!(prnt.text_chunks(&png).unwrap(), vec!["readable"]);
+
+        prnt.strict_text = true;
+        let err = prnt.text_chunks(&png).unwrap_err();
+        assert_eq!(err, "biNy chunk data is not valid UTF-8");
+    }
+
+    #[test]
+    fn test_print_hexdump_sizes() {
+        let path = temp_path("print_hexdump_sizes.png");
+        let png = Png::from_chunks(vec![Chunk::new(
+            ChunkType::from_str("biNy").unwrap(),
+            vec![0; 1536],
+        )]);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let mut prnt = Print {
+            file_path: vec![path.clone()],
+            strict_text: false,
+            max_chunks: Png::DEFAULT_MAX_CHUNKS,
+            max_chunk_size: Png::MAX_CHUNK_SIZE,
+            hexdump: true,
+            bytes: false,
+        };
+        assert!(prnt.render().unwrap().starts_with("biNy (1.50 KiB)\n"));
+
+        prnt.bytes = true;
+        assert!(prnt.render().unwrap().starts_with("biNy (1536 bytes)\n"));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_encode_rejects_empty_message() {
+        let enc = encode_args(PathBuf::new(), "ruSt", "");
+        assert!(enc
+            .process_req()
+            .unwrap_err()
+            .starts_with("Refusing to encode an empty message"));
+    }
+
+    #[test]
+    fn test_encode_force_structural_chunk() {
+        let input = testing_png_file("force_iend.png");
+        let mut enc = encode_args(input.clone(), "IEND", "early end");
+        assert_eq!(
+            enc.process_req().unwrap_err(),
+            "Refusing to insert another IHDR or IEND chunk, pass --force to insert it anyway"
+        );
+
+        enc.force = true;
+        let png = enc.process_req().unwrap();
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IEND", "IEND"]);
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_encode_allow_empty_message() {
+        let input = testing_png_file("allow_empty.png");
+        let enc = EncodeArgs {
+            allow_empty: true,
+            ..encode_args(input.clone(), "ruSt", "")
+        };
+
+        let png = enc.process_req().unwrap();
+        let chunk = png.chunk_by_type("ruSt").unwrap();
+        assert_eq!(chunk.length(), 0);
+        assert_eq!(chunk.crc(), crc32fast::hash(b"ruSt"));
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_decode_ignore_crc() {
+        let path = temp_path("ignore_crc.png");
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(with_crc(chunk_from_strings("ruSt", "still readable"), 1234).iter())
+            .copied()
+            .collect();
+        fs::write(&path, bytes).unwrap();
+
+        let dec = DecodeArgs {
+            ignore_crc: true,
+            ..decode_args(path.clone(), "ruSt")
+        };
+        assert_eq!(dec.process_req().unwrap(), "still readable");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hexdump() {
+        let data: Vec<u8> = b"Hello, PNG chunk!\x00\x7f\xff".to_vec();
+        let expected = "\
+00000000  48 65 6c 6c 6f 2c 20 50  4e 47 20 63 68 75 6e 6b  |Hello, PNG chunk|
+00000010  21 00 7f ff                                       |!...|";
+        assert_eq!(hexdump(&data[..]), expected);
+        assert_eq!(hexdump(&[]), "");
+    }
+
+    #[test]
+    fn test_write_png_reports_path() {
+        let path = temp_path("missing_dir").join("out.png");
+        let err = write_png(&path, &Png::new_empty()).unwrap_err();
+        assert!(err.starts_with(&format!("Failed to write {}: ", path.display())));
+    }
+
+    #[test]
+    fn test_validate() {
+        let valid = testing_png_file("validate_ok.png");
+        let bad_crc = temp_path("validate_crc.png");
+        let bytes: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(chunk_from_strings("IHDR", "header").as_bytes().iter())
+            .chain(with_crc(chunk_from_strings("ruSt", "message"), 1234).iter())
+            .chain(chunk_from_strings("IEND", "").as_bytes().iter())
+            .copied()
+            .collect();
+        fs::write(&bad_crc, bytes).unwrap();
+        let not_png = temp_path("validate_not.png");
+        fs::write(&not_png, b"GIF89a not a png").unwrap();
+
+        let mut args = ValidateArgs {
+            file_path: vec![valid.clone()],
+            all_errors: false,
+            sniff: false,
+        };
+        assert!(args.process_req());
+        assert_eq!(args.validate(&fs::read(&bad_crc).unwrap()[..]), vec!["ruSt chunk CRC mismatch"]);
+
+        let unordered: Vec<u8> = Png::STANDARD_HEADER
+            .iter()
+            .chain(with_crc(chunk_from_strings("ruSt", "message"), 1234).iter())
+            .chain(chunk_from_strings("IHDR", "header").as_bytes().iter())
+            .copied()
+            .collect();
+        assert_eq!(args.validate(&unordered[..]), vec!["first chunk is not IHDR"]);
+        args.all_errors = true;
+        assert_eq!(
+            args.validate(&unordered[..]),
+            vec!["first chunk is not IHDR", "ruSt chunk CRC mismatch", "last chunk is not IEND"]
+        );
+        assert_eq!(
+            args.validate(&unordered[..unordered.len() - 3]),
+            vec![
+                "first chunk is not IHDR",
+                "ruSt chunk CRC mismatch",
+                "chunk length exceeds available data"
+            ]
+        );
+        args.all_errors = false;
+
+        args.file_path = vec![bad_crc.clone()];
+        assert!(!args.process_req());
+        args.file_path = vec![not_png.clone()];
+        assert!(!args.process_req());
+        args.sniff = true;
+        assert!(!args.process_req());
+        args.file_path = vec![valid.clone()];
+        assert!(args.process_req());
+
+        for path in [valid, bad_crc, not_png].iter() {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_dump_chunk_reparses() {
+        let path = testing_png_file("dump_chunk.png");
+        let mut dump = DumpChunkArgs {
+            file_path: path.clone(),
+            index: Some(1),
+            chunk_type: None,
+            output_file: None,
+        };
+
+        let bytes = dump.process_req().unwrap();
+        let chunk = Chunk::try_from(&bytes[..]).unwrap();
+        assert_eq!(chunk, chunk_from_strings("IEND", ""));
+
+        dump.index = None;
+        dump.chunk_type = Some(String::from("IHDR"));
+        let bytes = dump.process_req().unwrap();
+        assert_eq!(Chunk::try_from(&bytes[..]).unwrap(), chunk_from_strings("IHDR", "header"));
+
+        dump.chunk_type = None;
+        dump.index = Some(5);
+        assert!(dump.process_req().is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_trim() {
+        let path = temp_path("decode_trim.png");
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "\n  padded message \t\n")]);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let mut dec = decode_args(path.clone(), "ruSt");
+        assert_eq!(dec.process_req().unwrap(), "\n  padded message \t\n");
+
+        dec.trim = true;
+        assert_eq!(dec.process_req().unwrap(), "padded message");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_sidecar() {
+        let path = temp_path("sidecar.png");
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "catalogued message")]);
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let dec = DecodeArgs {
+            sidecar: true,
+            ..decode_args(path.clone(), "ruSt")
+        };
+        dec.write_sidecar(&dec.process_req().unwrap()).unwrap();
+
+        let sidecar = temp_path("sidecar.png.ruSt.txt");
+        assert_eq!(dec.sidecar_path(), sidecar);
+        assert_eq!(fs::read_to_string(&sidecar).unwrap(), "catalogued message");
+        fs::remove_file(sidecar).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_range() {
+        assert_eq!(parse_range("2..5", 10), Ok((2, 5)));
+        assert_eq!(parse_range("4..", 10), Ok((4, 10)));
+        assert_eq!(parse_range("..3", 10), Ok((0, 3)));
+        assert_eq!(
+            parse_range("8..12", 10),
+            Err(String::from("Range 8..12 is out of bounds for 10 bytes of chunk data"))
+        );
+        assert!(parse_range("5..2", 10).is_err());
+        assert!(parse_range("5", 10).is_err());
+        assert!(parse_range("a..b", 10).is_err());
+
+        let path = temp_path("decode_range.png");
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "header:payload")]);
+        fs::write(&path, png.as_bytes()).unwrap();
+        let mut dec = DecodeArgs {
+            range: Some(String::from("..6")),
+            ..decode_args(path.clone(), "ruSt")
+        };
+        assert_eq!(dec.process_req().unwrap(), "header");
+
+        dec.range = Some(String::from("7.."));
+        assert_eq!(dec.process_req().unwrap(), "payload");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_decode_all() {
+        let path = temp_path("decode_all.png");
+        let png = Png::from_chunks(vec![
+            chunk_from_strings("ruSt", "first"),
+            chunk_from_strings("teXt", "other"),
+            chunk_from_strings("ruSt", "second"),
+            chunk_from_strings("ruSt", "third"),
+        ]);
+        fs::write(&path, png.as_bytes()).unwrap();
+        let mut dec = decode_args(path.clone(), "ruSt");
+        assert_eq!(dec.process_req().unwrap(), "first");
+
+        dec.all = true;
+        assert_eq!(dec.process_req().unwrap(), "first\nsecond\nthird");
+
+        dec.chunk_type = String::from("noNe");
+        assert_eq!(dec.process_req().unwrap_err(), "No noNe chunk in the PNG file");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_print_multiple_files() {
+        let first = temp_path("print_first.png");
+        let second = temp_path("print_second.png");
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "first message")]);
+        fs::write(&first, png.as_bytes()).unwrap();
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "second message")]);
+        fs::write(&second, png.as_bytes()).unwrap();
+
+        let prnt = Print {
+            file_path: vec![first.clone(), second.clone()],
+            strict_text: false,
+            max_chunks: Png::DEFAULT_MAX_CHUNKS,
+            max_chunk_size: Png::MAX_CHUNK_SIZE,
+            hexdump: false,
+            bytes: false,
+        };
+        let expected = format!(
+            "==> {} <==\nfirst message\n==> {} <==\nsecond message\n",
+            first.display(),
+            second.display()
+        );
+        assert_eq!(prnt.render().unwrap(), expected);
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn test_chunk_json() {
+        let text = chunk_from_strings("ruSt", "say \"hi\"\n");
+        assert_eq!(
+            chunk_json(&text),
+            format!(
+                "{{\"chunk_type\":\"ruSt\",\"length\":9,\"crc\":{},\"data_base64\":\"c2F5ICJoaSIK\",\"text\":\"say \\\"hi\\\"\\n\"}}",
+                text.crc()
+            )
+        );
+
+        let binary = Chunk::new(ChunkType::from_str("biNy").unwrap(), vec![0xff, 0x00, 0x10]);
+        assert_eq!(
+            chunk_json(&binary),
+            format!(
+                "{{\"chunk_type\":\"biNy\",\"length\":3,\"crc\":{},\"data_base64\":\"/wAQ\",\"text\":null}}",
+                binary.crc()
+            )
+        );
+    }
+
+    #[test]
+    fn test_decode_json_rejects_data_options() {
+        let options = [
+            DecodeArgs { range: Some(String::from("0..2")), ..decode_args(PathBuf::new(), "ruSt") },
+            DecodeArgs { trim: true, ..decode_args(PathBuf::new(), "ruSt") },
+            DecodeArgs { padded: true, ..decode_args(PathBuf::new(), "ruSt") },
+            DecodeArgs { hexdump: true, ..decode_args(PathBuf::new(), "ruSt") },
+        ];
+        for option in options {
+            let dec = DecodeArgs { format: String::from("json"), ..option };
+            assert_eq!(
+                dec.process_req().unwrap_err(),
+                "--format json can't be combined with --range, --trim, --padded or --hexdump"
+            );
+        }
+    }
+
+    #[test]
+    fn test_encode_pad_to() {
+        for message in ["short", "a much longer message than the other one"].iter() {
+            let input = testing_png_file("pad_to.png");
+            let enc = EncodeArgs {
+                pad_to: Some(64),
+                ..encode_args(input.clone(), "ruSt", message)
+            };
+            fs::write(&input, enc.process_req().unwrap().as_bytes()).unwrap();
+
+            let dec = DecodeArgs {
+                padded: true,
+                ..decode_args(input.clone(), "ruSt")
+            };
+            let png = Png::from_file(&input).unwrap();
+            assert_eq!(png.chunk_by_type("ruSt").unwrap().length(), 64);
+            assert_eq!(&dec.process_req().unwrap(), message);
+            fs::remove_file(input).unwrap();
+        }
+        assert!(pad_payload(&[0; 61], 64).is_err());
+    }
+
+    #[test]
+    fn test_encode_show_diff() {
+        let input = testing_png_file("show_diff.png");
+        let enc = EncodeArgs {
+            show_diff: true,
+            ..encode_args(input.clone(), "ruSt", "hidden")
+        };
+
+        let png = enc.process_req().unwrap();
+        let original = Png::from_file(&input).unwrap();
+        let index = png.find_chunk_index("ruSt").unwrap();
+        let start = png.chunk_offset(index).unwrap();
+        let end = start + 12 + "hidden".len();
+
+        assert_eq!(added_range(&original, &png), Some((start, end)));
+        assert_eq!(&png.as_bytes()[start + 4..start + 8], b"ruSt");
+        assert_eq!(
+            enc.diff_report(&png).unwrap(),
+            format!(
+                "bytes [{}..{}] added for new ruSt chunk ({} -> {})",
+                start,
+                end,
+                format_bytes(original.byte_len() as u64),
+                format_bytes(png.byte_len() as u64)
+            )
+        );
+        let raw = EncodeArgs { bytes: true, ..enc };
+        assert_eq!(
+            raw.diff_report(&png).unwrap(),
+            format!(
+                "bytes [{}..{}] added for new ruSt chunk ({} -> {} bytes)",
+                start,
+                end,
+                original.byte_len(),
+                png.byte_len()
+            )
+        );
+        assert_eq!(added_range(&original, &original), None);
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_order_signal_args() {
+        let input = testing_png_file("order_signal.png");
+        let enc = EncodeArgs {
+            order_signal: Some(String::from("10110010")),
+            message: None,
+            ..encode_args(input.clone(), "orDr", "")
+        };
+        fs::write(&input, enc.process_req().unwrap().as_bytes()).unwrap();
+
+        let dec = DecodeArgs {
+            order_signal: true,
+            ..decode_args(input.clone(), "orDr")
+        };
+        assert_eq!(dec.process_req().unwrap(), "10110010");
+        assert!(parse_bits("10x1").is_err());
+        assert!(parse_bits("").is_err());
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_normalize_chunk_type() {
+        assert_eq!(normalize_chunk_type(" ruSt\t").unwrap(), "ruSt");
+        assert_eq!(
+            normalize_chunk_type("ru1t").unwrap_err(),
+            "Invalid chunk type \"ru1t\": not an ASCII letter: '1' at position 3"
+        );
+        assert_eq!(
+            normalize_chunk_type("r-1t").unwrap_err(),
+            "Invalid chunk type \"r-1t\": not an ASCII letter: '-' at position 2, '1' at position 3"
+        );
+        assert_eq!(
+            normalize_chunk_type("ru").unwrap_err(),
+            "Invalid chunk type \"ru\": expected 4 ASCII letters, got 2 characters"
+        );
+        assert_eq!(
+            normalize_chunk_type(" ruSty").unwrap_err(),
+            "Invalid chunk type \" ruSty\": expected 4 ASCII letters, got 5 characters"
+        );
+    }
+
+    #[test]
+    fn test_encode_meta() {
+        let input = testing_png_file("meta.png");
+        let meta_file = temp_path("meta.txt");
+        fs::write(&meta_file, "Title=Ping pong\nAuthor=mogendi\n\nComment=a=b\n").unwrap();
+        let meta = MetaArgs {
+            file_path: input.clone(),
+            meta_file: meta_file.clone(),
+            output_file: None,
+        };
+
+        let png = meta.process_req().unwrap();
+        let texts: Vec<&[u8]> = png
+            .find_all_indices("tEXt")
+            .into_iter()
+            .map(|index| png.chunks()[index].data())
+            .collect();
+        assert_eq!(
+            texts,
+            vec![&b"Title\0Ping pong"[..], b"Author\0mogendi", b"Comment\0a=b"]
+        );
+        assert_eq!(png.chunk_types().last().unwrap(), "IEND");
+
+        fs::write(&meta_file, "Title=ok\nno separator\n").unwrap();
+        assert_eq!(meta.process_req().unwrap_err(), "Line 2 is not a keyword=value pair");
+        fs::write(&meta_file, "=empty keyword\n").unwrap();
+        assert_eq!(
+            meta.process_req().unwrap_err(),
+            "Line 1: tEXt keyword must be 1 to 79 bytes long"
+        );
+        fs::remove_file(meta_file).unwrap();
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_compare_msg() {
+        let first = temp_path("compare_first.png");
+        let second = temp_path("compare_second.png");
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "same message")]);
+        fs::write(&first, png.as_bytes()).unwrap();
+        fs::write(&second, png.as_bytes()).unwrap();
+        let compare = CompareMsgArgs {
+            first: first.clone(),
+            second: second.clone(),
+            chunk_type: String::from("ruSt"),
+        };
+        assert_eq!(compare.diff(), Ok(None));
+        assert!(compare.process_req());
+
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "same massage!")]);
+        fs::write(&second, png.as_bytes()).unwrap();
+        assert_eq!(
+            compare.diff(),
+            Ok(Some(String::from(
+                "messages differ at byte 6\n< same message\n> same massage!"
+            )))
+        );
+        assert!(!compare.process_req());
+
+        let missing = CompareMsgArgs {
+            chunk_type: String::from("noNe"),
+            ..compare
+        };
+        assert!(missing.diff().unwrap_err().starts_with("No noNe chunk in "));
+        assert!(!missing.process_req());
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn test_encode_fix_reserved_bit() {
+        let input = testing_png_file("fix_reserved_bit.png");
+        let mut enc = encode_args(input.clone(), "rust", "hidden");
+        assert!(!has_valid_reserved_bit(&enc.chunk_type().unwrap()));
+        assert!(enc.process_req().unwrap().contains_chunk_type("rust"));
+
+        enc.fix_reserved_bit = true;
+        assert_eq!(enc.chunk_type().unwrap(), "ruSt");
+        let png = enc.process_req().unwrap();
+        assert!(png.chunk_by_type("ruSt").unwrap().chunk_type().is_valid());
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_remove_writes_file() {
+        let input = testing_png_file("remove_in.png");
+        let output = temp_path("remove_out.png");
+        let mut png = Png::from_file(&input).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "hidden")).unwrap();
+        write_png(&input, &png).unwrap();
+
+        let rem = Remove {
+            file_path: input.clone(),
+            chunk_type: String::from("ruSt"),
+            output_file: Some(output.clone()),
+            journal: None,
+        };
+        write_png(&output, &rem.process_req().unwrap()).unwrap();
+        assert_eq!(Png::from_file(&output).unwrap().chunk_types(), vec!["IHDR", "IEND"]);
+        assert!(Png::from_file(&input).unwrap().contains_chunk_type("ruSt"));
+
+        let missing = Remove {
+            file_path: output.clone(),
+            chunk_type: String::from("ruSt"),
+            output_file: None,
+            journal: None,
+        };
+        assert_eq!(missing.process_req().unwrap_err(), "No ruSt chunk in the PNG file");
+        fs::remove_file(input).unwrap();
+        fs::remove_file(output).unwrap();
+    }
+
+    #[test]
+    fn test_remove_then_undo_restores_chunk() {
+        let input = testing_png_file("undo_remove.png");
+        let journal = temp_path("undo_remove.journal");
+        let mut png = Png::from_file(&input).unwrap();
+        png.append_chunk(chunk_from_strings("ruSt", "hidden")).unwrap();
+        write_png(&input, &png).unwrap();
+        let original = fs::read(&input).unwrap();
+
+        let rem = Remove {
+            file_path: input.clone(),
+            chunk_type: String::from("ruSt"),
+            output_file: None,
+            journal: Some(journal.clone()),
+        };
+        write_journaled(&input, &input, &rem.process_req().unwrap(), rem.journal.as_ref()).unwrap();
+        assert!(!Png::from_file(&input).unwrap().contains_chunk_type("ruSt"));
+
+        let undo = UndoArgs { journal: journal.clone() };
+        undo.process_req().unwrap();
+        assert_eq!(fs::read(&input).unwrap(), original);
+        assert_eq!(fs::read_to_string(&journal).unwrap(), "");
+        assert!(undo.process_req().unwrap_err().ends_with("has no changes to undo"));
+        fs::remove_file(input).unwrap();
+        fs::remove_file(journal).unwrap();
+    }
+
+    #[test]
+    fn test_undo_refuses_a_changed_file() {
+        let input = testing_png_file("undo_changed.png");
+        let journal = temp_path("undo_changed.journal");
+        let enc = EncodeArgs {
+            journal: Some(journal.clone()),
+            ..encode_args(input.clone(), "ruSt", "hidden")
+        };
+        write_journaled(&input, &input, &enc.process_req().unwrap(), enc.journal.as_ref()).unwrap();
+        let mut png = Png::from_file(&input).unwrap();
+        png.append_to_chunk("ruSt", b" and edited").unwrap();
+        write_png(&input, &png).unwrap();
+
+        let undo = UndoArgs { journal: journal.clone() };
+        assert!(undo.process_req().unwrap_err().ends_with("changed since the journaled edit, not undoing it"));
+        assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), 1);
+        fs::remove_file(input).unwrap();
+        fs::remove_file(journal).unwrap();
+    }
+
+    #[test]
+    fn test_journal_entry_round_trip() {
+        let before = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header"),
+            chunk_from_strings("ruSt", "old"),
+            chunk_from_strings("IEND", ""),
+        ]);
+        let mut after = before.clone();
+        after.append_to_chunk("ruSt", b" and new").unwrap();
+
+        let path = PathBuf::from("dir\twith tab/file.png");
+        let entry = JournalEntry::between(&path, &before, &after).unwrap();
+        assert_eq!(entry.start, 1);
+        assert_eq!(entry.removed, vec![chunk_from_strings("ruSt", "old")]);
+        assert_eq!(JournalEntry::parse(&entry.to_line()).unwrap(), entry);
+        assert_eq!(entry.reverse(&after).unwrap().as_bytes(), before.as_bytes());
+        assert_eq!(JournalEntry::between(&path, &before, &before), None);
+        assert!(JournalEntry::parse("1\tzz\t\tfile.png").is_err());
+    }
+
+    #[test]
+    fn test_append_missing_chunk_is_an_error() {
+        let input = testing_png_file("append_missing.png");
+        let app = AppendArgs {
+            file_path: input.clone(),
+            chunk_type: String::from("noNe"),
+            message: String::from("more"),
+            output_file: None,
+            journal: None,
+        };
+        assert_eq!(app.process_req().unwrap_err(), "No noNe chunk in the PNG file");
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.50 KiB");
+        assert_eq!(format_bytes(5 * 1024 * 1024), "5.00 MiB");
+        assert_eq!(format_bytes(3 << 40), "3072.00 GiB");
+    }
+
+    #[test]
+    fn test_encode_fill() {
+        let input = testing_png_file("fill.png");
+        let enc = EncodeArgs {
+            fill: Some(b'A'),
+            fill_count: Some(1000),
+            message: None,
+            ..encode_args(input.clone(), "ruSt", "")
+        };
+
+        let png = enc.process_req().unwrap();
+        let chunk = png.chunk_by_type("ruSt").unwrap();
+        assert_eq!(chunk.length(), 1000);
+        assert!(chunk.data().iter().all(|&byte| byte == b'A'));
+        fs::remove_file(input).unwrap();
+    }
+
+    #[test]
+    fn test_missing_file_error_displays_path() {
+        let path = temp_path("missing \"quoted\" file.png");
+        let dump = DumpChunkArgs {
+            file_path: path.clone(),
+            index: Some(0),
+            chunk_type: None,
+            output_file: None,
+        };
+
+        let err = dump.process_req().unwrap_err();
+        assert!(err.starts_with(&format!("Failed to read {}: ", path.display())));
+        assert!(!err.contains(&format!("{:?}", path)));
     }
 }
@@ -4,7 +4,7 @@ use std::fmt::{Debug, Display, Formatter};
 use std::str::{FromStr, from_utf8};
 use std::string::{FromUtf8Error, String};
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub struct Chunk {
     chunk_length: u32,
     chunk_type: ChunkType,
@@ -14,14 +14,11 @@ pub struct Chunk {
 
 impl Chunk {
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
-        let crc = crc32fast::hash(
-            &chunk_type
-                .chunk_type
-                .iter()
-                .chain(data.iter())
-                .copied()
-                .collect::<Vec<u8>>()[..],
-        );
+        Self::new_with_crc(chunk_type, data, &Crc32Fast)
+    }
+
+    pub fn new_with_crc(chunk_type: ChunkType, data: Vec<u8>, crc: &dyn Crc32) -> Chunk {
+        let crc = compute_crc_with(&chunk_type, &data[..], crc);
         Chunk {
             chunk_length: data.len() as u32,
             chunk_type: chunk_type,
@@ -55,6 +52,20 @@ impl Chunk {
             }
         }
     }
+    // Rebuild a chunk from its type and data, the CRC is
+    // recomputed rather than carried over
+    pub fn from_parts(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        Self::new(chunk_type, data)
+    }
+    // Replace the data, recomputing the length and CRC for it
+    pub fn with_data(mut self, data: Vec<u8>) -> Chunk {
+        self.chunk_data = data;
+        self.recompute_crc();
+        self
+    }
+    pub fn into_parts(self) -> (ChunkType, Vec<u8>) {
+        (self.chunk_type, self.chunk_data)
+    }
     pub fn length(&self) -> u32 {
         self.chunk_data.len().try_into().unwrap()
     }
@@ -67,6 +78,44 @@ impl Chunk {
     pub fn crc(&self) -> u32 {
         self.chunk_crc
     }
+    // whether the stored CRC matches the chunk's type and data
+    pub fn crc_matches(&self) -> bool {
+        self.crc_matches_with_crc(&Crc32Fast)
+    }
+    // Same as `crc_matches`, for chunks built with `new_with_crc`
+    pub fn crc_matches_with_crc(&self, crc: &dyn Crc32) -> bool {
+        compute_crc_with(&self.chunk_type, &self.chunk_data[..], crc) == self.chunk_crc
+    }
+    // Editing through `data_mut` leaves the stored length and CRC
+    // stale until `recompute_crc` is called, so it stays in the crate
+    pub(crate) fn data_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.chunk_data
+    }
+    // Check that the stored length and CRC agree with the data
+    // and that the type is valid, e.g. before serializing a
+    // chunk that was assembled or edited by hand
+    pub fn validate(&self) -> Result<(), &'static str> {
+        self.validate_with_crc(&Crc32Fast)
+    }
+    pub fn validate_with_crc(&self, crc: &dyn Crc32) -> Result<(), &'static str> {
+        if self.chunk_length as usize != self.chunk_data.len() {
+            return Err("Chunk length does not match its data");
+        }
+        if !self.crc_matches_with_crc(crc) {
+            return Err("Invalid chunk CRC");
+        }
+        if !self.chunk_type.is_valid() {
+            return Err("Invalid chunk type");
+        }
+        Ok(())
+    }
+    pub fn recompute_crc(&mut self) {
+        self.recompute_crc_with_crc(&Crc32Fast)
+    }
+    pub fn recompute_crc_with_crc(&mut self, crc: &dyn Crc32) {
+        self.chunk_length = self.chunk_data.len() as u32;
+        self.chunk_crc = compute_crc_with(&self.chunk_type, &self.chunk_data[..], crc);
+    }
     pub fn as_bytes(&self) -> Vec<u8> {
         self.chunk_length
             .to_be_bytes()
@@ -80,12 +129,61 @@ impl Chunk {
     pub fn data_as_string(&self) -> Result<String, FromUtf8Error> {
         String::from_utf8(self.chunk_data.clone())
     }
+    pub fn is_text(&self) -> bool {
+        from_utf8(&self.chunk_data).is_ok()
+    }
+    // invalid UTF-8 sequences show up as U+FFFD
+    pub fn text_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.chunk_data).into_owned()
+    }
+}
+
+// The checksum stored after each chunk's data, swappable so
+// files written by tools with a different CRC can be handled
+pub trait Crc32 {
+    fn hash(&self, data: &[u8]) -> u32;
+}
+
+// The standard PNG CRC-32
+pub struct Crc32Fast;
+
+impl Crc32 for Crc32Fast {
+    fn hash(&self, data: &[u8]) -> u32 {
+        crc32fast::hash(data)
+    }
+}
+
+// CRC over the chunk type and data, as stored after the data
+pub fn compute_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+    compute_crc_with(chunk_type, data, &Crc32Fast)
+}
+
+pub fn compute_crc_with(chunk_type: &ChunkType, data: &[u8], crc: &dyn Crc32) -> u32 {
+    crc.hash(&[&chunk_type.chunk_type[..], data].concat())
 }
 
 impl TryFrom<&[u8]> for Chunk {
     type Error = &'static str;
 
     fn try_from(source: &[u8]) -> Result<Self, Self::Error> {
+        Chunk::parse(source, Some(&Crc32Fast))
+    }
+}
+
+impl Chunk {
+    // Same as `try_from`, but a CRC that doesn't match the type and
+    // data is kept as-is instead of rejecting the chunk
+    pub fn try_from_ignoring_crc(source: &[u8]) -> Result<Chunk, &'static str> {
+        Chunk::parse(source, None)
+    }
+
+    // Same as `try_from`, checking the stored CRC with `crc`
+    // instead of the default crc32fast backed one
+    pub fn try_from_with_crc(source: &[u8], crc: &dyn Crc32) -> Result<Chunk, &'static str> {
+        Chunk::parse(source, Some(crc))
+    }
+
+    fn parse(source: &[u8], crc: Option<&dyn Crc32>) -> Result<Chunk, &'static str> {
         if source.len() < 12 {
             // Every PNG chunk byte source needs to
             // at leats have the PNG chunk header data
@@ -93,19 +191,25 @@ impl TryFrom<&[u8]> for Chunk {
         } else {
             let chunk_length: u32 = u32::from_be_bytes(source[0..4].try_into().unwrap());
             let chunk_type: [u8; 4] = source[4..8].try_into().unwrap();
-            let chunk_data: Vec<u8> = source[8..8 + chunk_length as usize]
-                .iter()
-                .copied()
-                .collect();
-            let chunk_crc =
-                u32::from_be_bytes(source[8 + chunk_length as usize..].try_into().unwrap());
-            println!("{}", chunk_crc);
-            if crc32fast::hash(&source[4..8 + chunk_length as usize]) != chunk_crc {
-                return Err("Invalid chunk CRC");
+            // the length field decides where the data ends and the
+            // CRC starts, so it has to agree with the source size
+            let data_end = 8 + chunk_length as usize;
+            if source.len() < data_end + 4 {
+                return Err("chunk length exceeds available data");
+            }
+            if source.len() > data_end + 4 {
+                return Err("chunk has bytes past its CRC");
+            }
+            let chunk_data: Vec<u8> = source[8..data_end].to_vec();
+            let chunk_crc = u32::from_be_bytes(source[data_end..].try_into().unwrap());
+            if let Some(crc) = crc {
+                if crc.hash(&source[4..data_end]) != chunk_crc {
+                    return Err("Invalid chunk CRC");
+                }
             }
             let chunk = Chunk {
                 chunk_length: chunk_length,
-                chunk_type: ChunkType::try_from(chunk_type).unwrap(),
+                chunk_type: ChunkType::try_from(chunk_type)?,
                 chunk_data: chunk_data,
                 chunk_crc: chunk_crc,
             };
@@ -116,7 +220,7 @@ impl TryFrom<&[u8]> for Chunk {
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", from_utf8(&self.chunk_data).unwrap())
+        write!(f, "{}", self.text_lossy())
     }
 }
 
@@ -130,25 +234,35 @@ impl Debug for Chunk {
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
     use crate::chunk_type::ChunkType;
     use std::str::FromStr;
 
-    fn testing_chunk() -> Chunk {
-        let data_length: u32 = 42;
-        let chunk_type = "RuSt".as_bytes();
-        let message_bytes = "This is where your secret message will be!".as_bytes();
-        let crc: u32 = 2882656334;
-
-        let chunk_data: Vec<u8> = data_length
+    // Serialize a chunk by hand around the given CRC, so fixtures
+    // can carry a deliberately wrong one
+    pub(crate) fn make_chunk_bytes(chunk_type: &str, data: &[u8], crc: u32) -> Vec<u8> {
+        (data.len() as u32)
             .to_be_bytes()
             .iter()
-            .chain(chunk_type.iter())
-            .chain(message_bytes.iter())
+            .chain(chunk_type.as_bytes().iter())
+            .chain(data.iter())
             .chain(crc.to_be_bytes().iter())
             .copied()
-            .collect();
+            .collect()
+    }
+
+    pub(crate) fn make_valid_chunk_bytes(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        let crc = compute_crc(&ChunkType::from_str(chunk_type).unwrap(), data);
+        make_chunk_bytes(chunk_type, data, crc)
+    }
+
+    fn testing_chunk() -> Chunk {
+        let chunk_data = make_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+            2882656334,
+        );
 
         Chunk::try_from(chunk_data.as_ref()).unwrap()
     }
@@ -181,19 +295,11 @@ mod tests {
 
     #[test]
     fn test_valid_chunk_from_bytes() {
-        let data_length: u32 = 42;
-        let chunk_type = "RuSt".as_bytes();
-        let message_bytes = "This is where your secret message will be!".as_bytes();
-        let crc: u32 = 2882656334;
-
-        let chunk_data: Vec<u8> = data_length
-            .to_be_bytes()
-            .iter()
-            .chain(chunk_type.iter())
-            .chain(message_bytes.iter())
-            .chain(crc.to_be_bytes().iter())
-            .copied()
-            .collect();
+        let chunk_data = make_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+            2882656334,
+        );
 
         let chunk = Chunk::try_from(chunk_data.as_ref()).unwrap();
 
@@ -207,20 +313,34 @@ mod tests {
     }
 
     #[test]
-    fn test_invalid_chunk_from_bytes() {
-        let data_length: u32 = 42;
-        let chunk_type = "RuSt".as_bytes();
-        let message_bytes = "This is where your secret message will be!".as_bytes();
-        let crc: u32 = 2882656333;
+    fn test_malformed_chunk_bytes_are_errors() {
+        let mut truncated = make_valid_chunk_bytes("RuSt", &[b'x'; 20]);
+        truncated[0..4].copy_from_slice(&1000u32.to_be_bytes());
+        assert_eq!(
+            Chunk::try_from(&truncated[..]),
+            Err("chunk length exceeds available data")
+        );
 
-        let chunk_data: Vec<u8> = data_length
-            .to_be_bytes()
-            .iter()
-            .chain(chunk_type.iter())
-            .chain(message_bytes.iter())
-            .chain(crc.to_be_bytes().iter())
-            .copied()
-            .collect();
+        let mut over_long = make_valid_chunk_bytes("RuSt", b"message");
+        over_long.extend_from_slice(b"junk");
+        assert_eq!(Chunk::try_from(&over_long[..]), Err("chunk has bytes past its CRC"));
+
+        let valid = make_valid_chunk_bytes("RuSt", b"message");
+        assert!(Chunk::try_from(&valid[..valid.len() - 1]).is_err());
+        assert!(Chunk::try_from(&valid[..11]).is_err());
+
+        let mut bad_type = make_chunk_bytes("RuSt", b"", 0);
+        bad_type[4..8].copy_from_slice(&[0xff; 4]);
+        assert!(Chunk::try_from_ignoring_crc(&bad_type[..]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_chunk_from_bytes() {
+        let chunk_data = make_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+            2882656333,
+        );
 
         let chunk = Chunk::try_from(chunk_data.as_ref());
 
@@ -228,20 +348,110 @@ mod tests {
     }
 
     #[test]
-    pub fn test_chunk_trait_impls() {
-        let data_length: u32 = 42;
-        let chunk_type = "RuSt".as_bytes();
-        let message_bytes = "This is where your secret message will be!".as_bytes();
-        let crc: u32 = 2882656334;
+    fn test_chunk_ignoring_crc() {
+        let chunk_data = make_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+            2882656333,
+        );
 
-        let chunk_data: Vec<u8> = data_length
-            .to_be_bytes()
-            .iter()
-            .chain(chunk_type.iter())
-            .chain(message_bytes.iter())
-            .chain(crc.to_be_bytes().iter())
-            .copied()
-            .collect();
+        let chunk = Chunk::try_from_ignoring_crc(chunk_data.as_ref()).unwrap();
+        assert_eq!(chunk.crc(), 2882656333);
+        assert!(!chunk.crc_matches());
+        assert!(testing_chunk().crc_matches());
+    }
+
+    #[test]
+    fn test_recompute_crc() {
+        let mut chunk = testing_chunk();
+        chunk.data_mut().truncate(13);
+        assert!(!chunk.crc_matches());
+
+        chunk.recompute_crc();
+        assert!(chunk.crc_matches());
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(chunk.as_bytes()[0..4], 13u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_with_data() {
+        let chunk = testing_chunk().with_data(b"new data".to_vec());
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.length(), 8);
+        assert_eq!(chunk.as_bytes()[0..4], 8u32.to_be_bytes());
+        assert_eq!(chunk.crc(), compute_crc(chunk.chunk_type(), b"new data"));
+        assert_eq!(chunk.data(), b"new data");
+    }
+
+    #[test]
+    fn test_validate() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.validate(), Ok(()));
+
+        let mut stale = testing_chunk();
+        stale.data_mut().push(b'!');
+        assert_eq!(stale.validate(), Err("Chunk length does not match its data"));
+
+        let bytes = make_chunk_bytes("RuSt", b"This is where your secret message will be!", 0);
+        let bad_crc = Chunk::try_from_ignoring_crc(&bytes[..]).unwrap();
+        assert_eq!(bad_crc.validate(), Err("Invalid chunk CRC"));
+
+        let reserved = Chunk::new(ChunkType::from_str("Rust").unwrap(), Vec::new());
+        assert_eq!(reserved.validate(), Err("Invalid chunk type"));
+    }
+
+    struct MockCrc;
+
+    impl Crc32 for MockCrc {
+        fn hash(&self, data: &[u8]) -> u32 {
+            data.len() as u32
+        }
+    }
+
+    #[test]
+    fn test_chunk_with_mock_crc() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let chunk = Chunk::new_with_crc(chunk_type, b"message".to_vec(), &MockCrc);
+        assert_eq!(chunk.crc(), 11);
+
+        let bytes = chunk.as_bytes();
+        assert!(Chunk::try_from(bytes.as_ref()).is_err());
+        assert_eq!(Chunk::try_from_with_crc(bytes.as_ref(), &MockCrc).unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_validate_with_mock_crc() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let mut chunk = Chunk::new_with_crc(chunk_type, b"message".to_vec(), &MockCrc);
+        assert!(chunk.crc_matches_with_crc(&MockCrc));
+        assert_eq!(chunk.validate_with_crc(&MockCrc), Ok(()));
+        assert_eq!(chunk.validate(), Err("Invalid chunk CRC"));
+
+        chunk.data_mut().extend_from_slice(b" and more");
+        chunk.recompute_crc_with_crc(&MockCrc);
+        assert_eq!(chunk.crc(), 20);
+        assert_eq!(chunk.validate_with_crc(&MockCrc), Ok(()));
+    }
+
+    #[test]
+    fn test_chunk_is_text() {
+        let chunk = testing_chunk();
+        assert!(chunk.is_text());
+        assert_eq!(chunk.text_lossy(), "This is where your secret message will be!");
+
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![b'h', b'i', 0xff]);
+        assert!(!chunk.is_text());
+        assert_eq!(chunk.text_lossy(), "hi\u{fffd}");
+        assert_eq!(chunk.to_string(), "hi\u{fffd}");
+    }
+
+    #[test]
+    pub fn test_chunk_trait_impls() {
+        let chunk_data = make_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+            2882656334,
+        );
 
         let chunk: Chunk = TryFrom::try_from(chunk_data.as_ref()).unwrap();
 
@@ -268,4 +478,25 @@ mod tests {
             crc
         )
     }
+
+    #[test]
+    fn test_make_valid_chunk_bytes() {
+        let chunk_data = make_valid_chunk_bytes(
+            "RuSt",
+            "This is where your secret message will be!".as_bytes(),
+        );
+        let chunk = Chunk::try_from(chunk_data.as_ref()).unwrap();
+        assert_eq!(chunk.crc(), 2882656334);
+    }
+
+    #[test]
+    pub fn test_chunk_parts_round_trip() {
+        let chunk = testing_chunk();
+        let (chunk_type, data) = chunk.clone().into_parts();
+        assert_eq!(chunk_type.to_string(), String::from("RuSt"));
+
+        let rebuilt = Chunk::from_parts(chunk_type, data);
+        assert_eq!(rebuilt, chunk);
+        assert_eq!(rebuilt.crc(), 2882656334);
+    }
 }
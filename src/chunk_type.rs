@@ -39,17 +39,50 @@ impl ChunkType {
     pub fn is_critical(&self) -> bool {
         self.is_critical
     }
-    fn is_public(&self) -> bool {
+    pub fn is_public(&self) -> bool {
         self.is_public
     }
-    fn is_reserved_bit_valid(&self) -> bool {
+    pub fn is_reserved_bit_valid(&self) -> bool {
         self.is_reserved_bit_valid
     }
-    fn is_safe_to_copy(&self) -> bool {
+    pub fn is_safe_to_copy(&self) -> bool {
         self.is_safe_to_copy
     }
 }
 
+impl ChunkType {
+    // Derive a type code from a label by hashing it into four letters,
+    // cased so the type is always ancillary, private, safe to copy
+    // and has a valid reserved bit
+    pub fn from_label(label: &str) -> ChunkType {
+        let hash = crc32fast::hash(label.as_bytes()).to_be_bytes();
+        let mut value = [0u8; 4];
+        for (i, byte) in hash.iter().enumerate() {
+            value[i] = b'a' + byte % 26;
+        }
+        value[2] = value[2].to_ascii_uppercase();
+        ChunkType::try_from(value).unwrap()
+    }
+    // Match against a glob where `?` is any one letter and `*`
+    // any run of letters, e.g. `??Xt` or `te*`
+    pub fn matches_glob(&self, pattern: &str) -> bool {
+        fn matches(pattern: &[u8], value: &[u8]) -> bool {
+            match (pattern.split_first(), value.split_first()) {
+                (None, _) => value.is_empty(),
+                (Some((b'*', rest)), _) => {
+                    matches(rest, value) || (!value.is_empty() && matches(pattern, &value[1..]))
+                }
+                (Some((b'?', rest)), Some((_, value))) => matches(rest, value),
+                (Some((expected, rest)), Some((actual, value))) => {
+                    expected == actual && matches(rest, value)
+                }
+                (Some(_), None) => false,
+            }
+        }
+        matches(pattern.as_bytes(), &self.chunk_type)
+    }
+}
+
 impl TryFrom<[u8; 4]> for ChunkType {
     type Error = &'static str;
 
@@ -80,10 +113,11 @@ impl FromStr for ChunkType {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        for i in 0..4 {
-            if !s.chars().nth(i).unwrap().is_alphabetic() {
-                return Err("Invalid utf8 characters in value");
-            }
+        if s.len() != 4 {
+            return Err("Chunk type must be 4 characters long");
+        }
+        if !s.bytes().all(|byte| byte.is_ascii_alphabetic()) {
+            return Err("Invalid utf8 characters in value");
         }
         let value: [u8; 4] = s.as_bytes().to_owned().try_into().unwrap();
         let mut chunk = ChunkType {
@@ -178,6 +212,19 @@ mod tests {
         assert!(chunk.is_valid());
     }
 
+    #[test]
+    pub fn test_matches_glob() {
+        let chunk = ChunkType::from_str("teXt").unwrap();
+        assert!(chunk.matches_glob("teXt"));
+        assert!(chunk.matches_glob("??Xt"));
+        assert!(chunk.matches_glob("te*"));
+        assert!(chunk.matches_glob("*t"));
+        assert!(chunk.matches_glob("*"));
+        assert!(!chunk.matches_glob("??xt"));
+        assert!(!chunk.matches_glob("te?"));
+        assert!(!chunk.matches_glob("teXtt"));
+    }
+
     #[test]
     pub fn test_invalid_chunk_is_valid() {
         let chunk = ChunkType::from_str("Rust").unwrap();
@@ -185,6 +232,20 @@ mod tests {
 
         let chunk = ChunkType::from_str("Ru1t");
         assert!(chunk.is_err());
+
+        assert!(ChunkType::from_str("Ru").is_err());
+        assert!(ChunkType::from_str("RuStY").is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_from_label() {
+        let chunk = ChunkType::from_label("holiday photos");
+        assert_eq!(chunk, ChunkType::from_label("holiday photos"));
+        assert_eq!(ChunkType::from_str(&chunk.to_string()).unwrap(), chunk);
+        assert!(chunk.is_valid());
+        assert!(!chunk.is_critical());
+        assert!(!chunk.is_public());
+        assert!(chunk.is_safe_to_copy());
     }
 
     #[test]
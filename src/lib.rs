@@ -0,0 +1,7 @@
+#![feature(drain_filter)]
+
+// The PNG chunk types and parsing behind the ping_pong CLI, usable
+// on their own as `ping_pong::png::Png` and `ping_pong::chunk::Chunk`
+pub mod chunk;
+pub mod chunk_type;
+pub mod png;
@@ -1,33 +1,93 @@
-#![feature(drain_filter)]
-
 mod args;
-mod chunk;
-mod chunk_type;
-mod png;
 
-use args::PngArgs;
-use std::fs;
+use args::{write_bytes, write_journaled, write_png, PngArgs};
+use std::io::{self, Write};
+use std::process;
 use structopt::StructOpt;
 
 fn main() {
     let args: PngArgs = PngArgs::from_args();
     match args{
         PngArgs::Encode(enc) => {
-            let png = enc.process_req();
-            if let Some(output_file) = enc.output_file {
-                fs::write(output_file, png.as_bytes()).unwrap();
-            } else {
-                fs::write(enc.file_path, png.as_bytes()).unwrap();
-            }
+            let output_file = enc.output_file.as_ref().unwrap_or(&enc.file_path);
+            exit_on_err(enc.process_req().and_then(|png| {
+                if enc.estimate_only {
+                    println!("{}", enc.format_size(png.byte_len()));
+                    Ok(())
+                } else if enc.show_diff {
+                    enc.diff_report(&png).map(|report| println!("{}", report))
+                } else {
+                    write_journaled(&enc.file_path, output_file, &png, enc.journal.as_ref())
+                }
+            }));
         }
-        PngArgs::Decode(dec) => {
-            println!("{}", dec.process_req());
+        PngArgs::Decode(dec) => match dec.process_req() {
+            Ok(message) => {
+                if dec.sidecar {
+                    exit_on_err(dec.write_sidecar(&message));
+                }
+                println!("{}", message);
+            }
+            Err(msg) => exit_on_err(Err(msg)),
+        },
+        PngArgs::Append(app) => {
+            let output_file = app.output_file.as_ref().unwrap_or(&app.file_path);
+            exit_on_err(app.process_req().and_then(|png| {
+                write_journaled(&app.file_path, output_file, &png, app.journal.as_ref())
+            }));
         }
         PngArgs::Remove(rem) => {
-            rem.process_req();
+            let output_file = rem.output_file.as_ref().unwrap_or(&rem.file_path);
+            exit_on_err(rem.process_req().and_then(|png| {
+                write_journaled(&rem.file_path, output_file, &png, rem.journal.as_ref())
+            }));
         }
         PngArgs::Print(prnt) => {
-            prnt.process_req();
+            exit_on_err(prnt.process_req());
+        }
+        PngArgs::CheckType(check) => {
+            if !check.process_req() {
+                process::exit(1);
+            }
+        }
+        PngArgs::Shuffle(shuf) => {
+            let output_file = shuf.output_file.as_ref().unwrap_or(&shuf.file_path);
+            exit_on_err(shuf.process_req().and_then(|png| write_png(output_file, &png)));
+        }
+        PngArgs::MergeIdat(merge) => {
+            let output_file = merge.output_file.as_ref().unwrap_or(&merge.file_path);
+            exit_on_err(merge.process_req().and_then(|png| write_png(output_file, &png)));
+        }
+        PngArgs::EncodeMeta(meta) => {
+            let output_file = meta.output_file.as_ref().unwrap_or(&meta.file_path);
+            exit_on_err(meta.process_req().and_then(|png| write_png(output_file, &png)));
         }
+        PngArgs::CompareMsg(cmp) => {
+            if !cmp.process_req() {
+                process::exit(1);
+            }
+        }
+        PngArgs::Validate(val) => {
+            if !val.process_req() {
+                process::exit(1);
+            }
+        }
+        PngArgs::DumpChunk(dump) => {
+            exit_on_err(dump.process_req().and_then(|bytes| match &dump.output_file {
+                Some(output_file) => write_bytes(output_file, &bytes[..]),
+                None => io::stdout()
+                    .write_all(&bytes)
+                    .map_err(|err| format!("Failed to write to stdout: {}", err)),
+            }));
+        }
+        PngArgs::DumpCrcTable(dump) => exit_on_err(dump.process_req()),
+        PngArgs::Undo(undo) => exit_on_err(undo.process_req()),
+    }
+}
+
+fn exit_on_err(result: Result<(), String>) {
+    if let Err(msg) = result {
+        eprintln!("{}", msg);
+        process::exit(1);
     }
 }
@@ -1,11 +1,30 @@
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, Crc32, Crc32Fast};
+use crate::chunk_type::ChunkType;
 use std::convert::TryFrom;
 use std::fmt::{Display, Formatter};
 use std::fs;
+use std::io::Read;
 use std::path::Path;
-use std::str::from_utf8;
+use std::str::{from_utf8, FromStr};
 
-#[derive(Debug)]
+// Bounds applied while parsing, so a crafted file can't make
+// the parser read unbounded chunks or allocate huge ones
+#[derive(Debug, Clone, Copy)]
+pub struct ParseLimits {
+    pub max_chunks: usize,
+    pub max_chunk_size: u32,
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        ParseLimits {
+            max_chunks: Png::DEFAULT_MAX_CHUNKS,
+            max_chunk_size: Png::MAX_CHUNK_SIZE,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
@@ -13,6 +32,9 @@ pub struct Png {
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    pub const DEFAULT_MAX_CHUNKS: usize = 100_000;
+    // the largest chunk length the PNG spec allows
+    pub const MAX_CHUNK_SIZE: u32 = (1 << 31) - 1;
 
     pub fn new_empty() -> Png {
         Png {
@@ -30,7 +52,101 @@ impl Png {
         let contents: Vec<u8> = fs::read(path).unwrap();
         Self::try_from(&contents[..])
     }
-    pub fn append_chunk(&mut self, chunk: Chunk) {
+    // Quick check of only the first 8 bytes against the PNG
+    // signature, without reading or parsing any chunks
+    pub fn sniff<P: AsRef<Path>>(path: P) -> bool {
+        let mut signature = [0u8; 8];
+        match fs::File::open(path) {
+            Ok(mut file) => {
+                file.read_exact(&mut signature).is_ok() && signature == Self::STANDARD_HEADER
+            }
+            Err(_) => false,
+        }
+    }
+    pub fn try_from_ignoring_crc(source: &[u8], limits: ParseLimits) -> Result<Png, &'static str> {
+        Self::parse(source, limits, None)
+    }
+    // Parse like `try_from`, but give up on files with more chunks
+    // or bigger chunks than `limits` allows
+    pub fn try_from_with_limit(source: &[u8], limits: ParseLimits) -> Result<Png, &'static str> {
+        Self::parse(source, limits, Some(&Crc32Fast))
+    }
+    pub fn try_from_with_crc(source: &[u8], crc: &dyn Crc32) -> Result<Png, &'static str> {
+        Self::parse(source, ParseLimits::default(), Some(crc))
+    }
+    // `crc` checks each chunk's stored CRC, or skips the check when None
+    fn parse(
+        source: &[u8],
+        limits: ParseLimits,
+        crc: Option<&dyn Crc32>,
+    ) -> Result<Png, &'static str> {
+        let mut png = Png::new_empty();
+        // check if the source is a PNG file first
+        if source.len() < 8 || source[0..8] != Self::STANDARD_HEADER {
+            return Err("Invalid PNG file format");
+        }
+        let mut cursor = 8;
+        let source_len = source.len();
+        while cursor < source_len {
+            if png.chunks.len() == limits.max_chunks {
+                return Err("chunk count limit exceeded");
+            }
+            if source_len - cursor < 8 {
+                return Err("Truncated chunk header");
+            }
+            let len = u32::from_be_bytes(source[cursor..cursor + 4].try_into().unwrap());
+            // checked before the chunk data is sliced and copied
+            if len > limits.max_chunk_size {
+                return Err("chunk size limit exceeded");
+            }
+            if cursor + 12 + len as usize > source_len {
+                return Err("chunk length exceeds available data");
+            }
+            let is_iend = &source[cursor + 4..cursor + 8] == b"IEND";
+
+            let chunk_bytes = &source[cursor..len as usize + cursor + 12];
+            let chunk = match crc {
+                Some(crc) => Chunk::try_from_with_crc(chunk_bytes, crc),
+                None => Chunk::try_from_ignoring_crc(chunk_bytes),
+            };
+            match chunk {
+                Ok(chunk) => {
+                    cursor = len as usize + cursor + 12;
+                    png.chunks.push(chunk);
+                    if is_iend {
+                        return Ok(png);
+                    }
+                }
+                Err(msg) => return Err(msg),
+            }
+        }
+        Ok(png)
+    }
+    // Insert before IEND, refusing a second IHDR or IEND since
+    // a PNG has exactly one of each. A PNG whose last chunk is not
+    // IEND (stripped or truncated) gets the chunk at the very end,
+    // check `ends_with_iend` first to warn about that
+    pub fn append_chunk(&mut self, chunk: Chunk) -> Result<(), &'static str> {
+        if Self::is_structural(&chunk) {
+            return Err("Refusing to insert another IHDR or IEND chunk");
+        }
+        self.force_append_chunk(chunk);
+        Ok(())
+    }
+    // Copy of this PNG with `chunk` appended, leaving `self` as it is
+    pub fn clone_with_chunk(&self, chunk: Chunk) -> Result<Png, &'static str> {
+        let mut png = self.clone();
+        png.append_chunk(chunk)?;
+        Ok(png)
+    }
+    pub fn ends_with_iend(&self) -> bool {
+        match self.chunks.last() {
+            Some(chunk) => &chunk.chunk_type().chunk_type == b"IEND",
+            None => false,
+        }
+    }
+    // `append_chunk` without the IHDR and IEND check
+    pub fn force_append_chunk(&mut self, chunk: Chunk) {
         // assuming the png was already in a valid state
         // the IEND chunk should be the last chunk of a
         // PNG file
@@ -51,6 +167,83 @@ impl Png {
             }
         }
     }
+    // Append an already serialized chunk, keeping its CRC
+    // as long as it matches the chunk's type and data
+    pub fn push_raw_chunk(&mut self, bytes: &[u8]) -> Result<(), &'static str> {
+        if bytes.len() < 12
+            || u32::from_be_bytes(bytes[0..4].try_into().unwrap()) as usize != bytes.len() - 12
+        {
+            return Err("Raw chunk length does not match its length field");
+        }
+        self.append_chunk(Chunk::try_from(bytes)?)
+    }
+    // Extend the data of the first chunk of `chunk_type`,
+    // updating its length and CRC to match
+    pub fn append_to_chunk(&mut self, chunk_type: &str, extra: &[u8]) -> Result<(), &'static str> {
+        match self.find_chunk_index(chunk_type) {
+            Some(index) => {
+                let chunk = &mut self.chunks[index];
+                chunk.data_mut().extend_from_slice(extra);
+                chunk.recompute_crc();
+                Ok(())
+            }
+            None => Err("Chunk not in the PNG struct"),
+        }
+    }
+    // Insert every chunk before IEND in order, after checking
+    // that all of them have valid types and matching CRCs
+    pub fn extend_chunks<I: IntoIterator<Item = Chunk>>(
+        &mut self,
+        chunks: I,
+    ) -> Result<(), &'static str> {
+        let chunks: Vec<Chunk> = chunks.into_iter().collect();
+        for chunk in chunks.iter() {
+            if !chunk.chunk_type().is_valid() {
+                return Err("Invalid chunk type");
+            }
+            if !chunk.crc_matches() {
+                return Err("Invalid chunk CRC");
+            }
+            if Self::is_structural(chunk) {
+                return Err("Refusing to insert another IHDR or IEND chunk");
+            }
+        }
+        for chunk in chunks {
+            self.force_append_chunk(chunk);
+        }
+        Ok(())
+    }
+    // Build a tEXt chunk from a keyword and text and insert it before
+    // IEND, both encoded as Latin-1 as the PNG spec requires
+    pub fn insert_text(&mut self, keyword: &str, text: &str) -> Result<(), &'static str> {
+        let keyword = match Self::latin1(keyword) {
+            Some(keyword) => keyword,
+            None => return Err("tEXt keyword is not Latin-1"),
+        };
+        if keyword.is_empty() || keyword.len() > 79 {
+            return Err("tEXt keyword must be 1 to 79 bytes long");
+        }
+        if keyword.iter().any(|&byte| byte < 32 || (127..161).contains(&byte)) {
+            return Err("tEXt keyword may only hold printable Latin-1 characters");
+        }
+        let mut data = keyword;
+        data.push(0);
+        match Self::latin1(text) {
+            Some(text) => data.extend(text),
+            None => return Err("tEXt text is not Latin-1"),
+        }
+        self.append_chunk(Chunk::new(ChunkType::from_str("tEXt").unwrap(), data))
+    }
+    fn latin1(value: &str) -> Option<Vec<u8>> {
+        value
+            .chars()
+            .map(|c| if (c as u32) < 256 { Some(c as u8) } else { None })
+            .collect()
+    }
+    fn is_structural(chunk: &Chunk) -> bool {
+        let chunk_type = &chunk.chunk_type().chunk_type;
+        chunk_type == b"IHDR" || chunk_type == b"IEND"
+    }
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, &'static str> {
         let removed_chunks: Vec<_> = self
             .chunks
@@ -62,19 +255,192 @@ impl Png {
             return Ok(removed_chunks[0].clone());
         }
     }
+    // Remove every ancillary chunk whose type matches the glob
+    // `pattern`, critical chunks are always kept
+    pub fn remove_matching(&mut self, pattern: &str) -> Vec<Chunk> {
+        let (removed, kept) = self.chunks.drain(..).partition(|chunk: &Chunk| {
+            !chunk.chunk_type().is_critical() && chunk.chunk_type().matches_glob(pattern)
+        });
+        self.chunks = kept;
+        removed
+    }
+    pub fn retain_chunks<F: Fn(&Chunk) -> bool>(&mut self, f: F) {
+        self.chunks.retain(|chunk| f(chunk));
+    }
+    // `f` gets each chunk's type and data to edit in place,
+    // the length and CRC are recomputed after it runs
+    pub fn map_chunks<F: FnMut(&ChunkType, &mut Vec<u8>)>(&mut self, mut f: F) {
+        for chunk in self.chunks.iter_mut() {
+            let chunk_type = chunk.chunk_type().clone();
+            f(&chunk_type, chunk.data_mut());
+            chunk.recompute_crc();
+        }
+    }
+    // Recompute every chunk's length and CRC from its current data,
+    // e.g. before saving chunks that were edited by hand
+    pub fn recompute_crcs(&mut self) {
+        self.map_chunks(|_, _| {});
+    }
+    // IHDR has to stay the first chunk and IEND the last,
+    // so swaps that would move either are refused
+    pub fn swap_chunks(&mut self, i: usize, j: usize) -> Result<(), &'static str> {
+        let count = self.chunks.len();
+        if i >= count || j >= count {
+            return Err("Chunk index out of range");
+        }
+        if i == j {
+            return Ok(());
+        }
+        for &index in [i, j].iter() {
+            let chunk_type = &self.chunks[index].chunk_type().chunk_type;
+            if (index == 0 && chunk_type == b"IHDR") || (index == count - 1 && chunk_type == b"IEND") {
+                return Err("IHDR must stay first and IEND must stay last");
+            }
+        }
+        self.chunks.swap(i, j);
+        Ok(())
+    }
+    // Join the data of every IDAT chunk into one IDAT at the position
+    // of the first; the image data is a single zlib stream split
+    // across IDATs, so the joined data decompresses the same way
+    pub fn merge_idat(&mut self) -> Result<(), &'static str> {
+        let indices = self.find_all_indices("IDAT");
+        let first = match indices.first() {
+            Some(&first) => first,
+            None => return Err("No IDAT chunk in the PNG file"),
+        };
+        let data: Vec<u8> = indices
+            .iter()
+            .flat_map(|&index| self.chunks[index].data().iter())
+            .copied()
+            .collect();
+        let merged = Chunk::new(self.chunks[first].chunk_type().clone(), data);
+        for &index in indices.iter().rev() {
+            self.chunks.remove(index);
+        }
+        self.chunks.insert(first, merged);
+        Ok(())
+    }
+    // Randomly permute each run of ancillary chunks between two
+    // critical chunks, so critical chunks keep their positions and
+    // chunks that must precede IDAT (gAMA, pHYs...) stay before it
+    pub fn shuffle_ancillary(&mut self, seed: u64) {
+        // xorshift64 gets stuck on a zero state
+        let mut state = if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed };
+        let mut start = 0;
+        while start < self.chunks.len() {
+            let run = self.chunks[start..]
+                .iter()
+                .take_while(|chunk| !chunk.chunk_type().is_critical())
+                .count();
+            for i in (1..run).rev() {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                let j = (state % (i as u64 + 1)) as usize;
+                self.chunks.swap(start + i, start + j);
+            }
+            start += run + 1;
+        }
+    }
     pub fn header(&self) -> &[u8; 8] {
         &self.header
     }
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks[..]
     }
+    pub fn chunk_types(&self) -> Vec<String> {
+        self.chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
-        for chunk in self.chunks().iter() {
-            if chunk.chunk_type().chunk_type == chunk_type.as_bytes() {
-                return Some(&chunk);
+        self.find_chunk_index(chunk_type)
+            .map(|index| &self.chunks[index])
+    }
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        self.find_all_indices(chunk_type)
+            .into_iter()
+            .map(|index| &self.chunks[index])
+            .collect()
+    }
+    pub fn contains_chunk_type(&self, chunk_type: &str) -> bool {
+        self.find_chunk_index(chunk_type).is_some()
+    }
+    pub fn find_chunk_index(&self, chunk_type: &str) -> Option<usize> {
+        self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().chunk_type == chunk_type.as_bytes())
+    }
+    pub fn find_all_indices(&self, chunk_type: &str) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().chunk_type == chunk_type.as_bytes())
+            .map(|(index, _)| index)
+            .collect()
+    }
+    // Join the data of every chunk whose type starts with `prefix`,
+    // ordered by the big-endian u32 index leading each chunk's data
+    pub fn reassemble(&self, prefix: &str) -> Result<Vec<u8>, &'static str> {
+        let mut parts: Vec<(u32, &[u8])> = Vec::new();
+        for chunk in self.chunks.iter() {
+            if !chunk.chunk_type().chunk_type.starts_with(prefix.as_bytes()) {
+                continue;
+            }
+            if chunk.data().len() < 4 {
+                return Err("Chunk is too short to hold a part index");
             }
+            let index = u32::from_be_bytes(chunk.data()[0..4].try_into().unwrap());
+            parts.push((index, &chunk.data()[4..]));
         }
-        None
+        if parts.is_empty() {
+            return Err("No chunks match the type prefix");
+        }
+        parts.sort_by_key(|(index, _)| *index);
+        Ok(parts.iter().flat_map(|(_, data)| data.iter()).copied().collect())
+    }
+    // Append two marker chunks per bit, each holding its big-endian
+    // u32 sequence number; a swapped pair encodes a 1
+    pub fn embed_order_signal(
+        &mut self,
+        chunk_type: &ChunkType,
+        bits: &[bool],
+    ) -> Result<(), &'static str> {
+        let mut markers = Vec::new();
+        for (i, &bit) in bits.iter().enumerate() {
+            let pair = [2 * i as u32, 2 * i as u32 + 1];
+            let order = if bit { [pair[1], pair[0]] } else { pair };
+            for sequence in order.iter() {
+                markers.push(Chunk::new(chunk_type.clone(), sequence.to_be_bytes().to_vec()));
+            }
+        }
+        self.extend_chunks(markers)
+    }
+    // Recover the bits written by `embed_order_signal` from the order
+    // the marker chunks of `chunk_type` appear in
+    pub fn read_order_signal(&self, chunk_type: &str) -> Result<Vec<bool>, &'static str> {
+        let indices = self.find_all_indices(chunk_type);
+        if indices.is_empty() || indices.len() % 2 == 1 {
+            return Err("Order signal needs an even, non-zero number of marker chunks");
+        }
+        let mut positions = vec![None; indices.len()];
+        for (position, &index) in indices.iter().enumerate() {
+            let data = self.chunks[index].data();
+            if data.len() != 4 {
+                return Err("Marker chunk does not hold a sequence number");
+            }
+            let sequence = u32::from_be_bytes(data.try_into().unwrap()) as usize;
+            match positions.get_mut(sequence) {
+                Some(slot @ None) => *slot = Some(position),
+                _ => return Err("Marker sequence numbers are not a permutation"),
+            }
+        }
+        Ok(positions
+            .chunks(2)
+            .map(|pair| pair[1] < pair[0])
+            .collect())
     }
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut png_vec: Vec<u8> = Vec::new();
@@ -85,35 +451,59 @@ impl Png {
 
         png_vec
     }
+    // Byte offset of the chunk at `index` within `as_bytes()`,
+    // pointing at the start of its length field
+    pub fn chunk_offset(&self, index: usize) -> Option<usize> {
+        if index >= self.chunks.len() {
+            return None;
+        }
+        Some(
+            self.header.len()
+                + self.chunks[..index]
+                    .iter()
+                    .map(|chunk| 12 + chunk.length() as usize)
+                    .sum::<usize>(),
+        )
+    }
+    // Size of `as_bytes()` without serializing: the signature plus
+    // 12 bytes of length, type and CRC around each chunk's data
+    pub fn byte_len(&self) -> usize {
+        self.header.len()
+            + self
+                .chunks()
+                .iter()
+                .map(|chunk| 12 + chunk.length() as usize)
+                .sum::<usize>()
+    }
+    // Walk the chunks in `source` without rejecting bad CRCs,
+    // pairing each chunk's stored CRC with a freshly computed one
+    pub fn crc_table(source: &[u8]) -> Result<Vec<(ChunkType, u32, u32)>, &'static str> {
+        if source.len() < 8 || source[0..8] != Self::STANDARD_HEADER {
+            return Err("Invalid PNG file format");
+        }
+        let mut rows = Vec::new();
+        let mut cursor = 8;
+        while cursor + 12 <= source.len() {
+            let len = u32::from_be_bytes(source[cursor..cursor + 4].try_into().unwrap()) as usize;
+            let crc_start = cursor + 8 + len;
+            if crc_start + 4 > source.len() {
+                return Err("Chunk length exceeds available data");
+            }
+            let type_bytes: [u8; 4] = source[cursor + 4..cursor + 8].try_into().unwrap();
+            let stored = u32::from_be_bytes(source[crc_start..crc_start + 4].try_into().unwrap());
+            let computed = crc32fast::hash(&source[cursor + 4..crc_start]);
+            rows.push((ChunkType::try_from(type_bytes)?, stored, computed));
+            cursor = crc_start + 4;
+        }
+        Ok(rows)
+    }
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = &'static str;
 
     fn try_from(source: &[u8]) -> Result<Png, Self::Error> {
-        let mut png = Png::new_empty();
-        // check if the source is a PNG file first
-        if source[0..8] != Self::STANDARD_HEADER {
-            return Err("Invalid PNG file format");
-        }
-        let mut cursor = 8;
-        let source_len = source.len();
-        for _ in cursor..source_len {
-            let len = u32::from_be_bytes(source[cursor..cursor + 4].try_into().unwrap());
-            let type_ = from_utf8(&source[cursor + 4..cursor + 8]).unwrap();
-
-            match Chunk::try_from(&source[cursor..len as usize + cursor + 12]) {
-                Ok(chunk) => {
-                    cursor = len as usize + cursor + 12;
-                    png.append_chunk(chunk);
-                    if type_ == "IEND" || cursor == source_len {
-                        return Ok(png);
-                    }
-                }
-                Err(msg) => return Err(msg),
-            }
-        }
-        Ok(png)
+        Png::try_from_with_limit(source, ParseLimits::default())
     }
 }
 
@@ -126,6 +516,7 @@ impl Display for Png {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::chunk::tests::make_chunk_bytes;
     use crate::chunk::Chunk;
     use crate::chunk_type::ChunkType;
     use std::convert::TryFrom;
@@ -220,6 +611,60 @@ mod tests {
         assert!(png.is_err());
     }
 
+    #[test]
+    fn test_max_chunks_exceeded() {
+        let bytes = testing_png().as_bytes();
+
+        let mut limits = ParseLimits {
+            max_chunks: 3,
+            ..ParseLimits::default()
+        };
+        assert!(Png::try_from_with_limit(bytes.as_ref(), limits).is_ok());
+        limits.max_chunks = 2;
+        assert_eq!(
+            Png::try_from_with_limit(bytes.as_ref(), limits).unwrap_err(),
+            "chunk count limit exceeded"
+        );
+    }
+
+    #[test]
+    fn test_max_chunk_size_exceeded() {
+        // declares far more data than the file holds, so it
+        // must be rejected before the data is sliced
+        let mut chunk = make_chunk_bytes("ruSt", &[1, 2, 3], 0);
+        chunk[0..4].copy_from_slice(&(1u32 << 20).to_be_bytes());
+        let bytes: Vec<u8> = Png::STANDARD_HEADER.iter().chain(chunk.iter()).copied().collect();
+
+        let limits = ParseLimits {
+            max_chunk_size: 1024,
+            ..ParseLimits::default()
+        };
+        assert_eq!(
+            Png::try_from_with_limit(bytes.as_ref(), limits).unwrap_err(),
+            "chunk size limit exceeded"
+        );
+    }
+
+    #[test]
+    fn test_png_with_mock_crc() {
+        use std::str::FromStr;
+
+        struct MockCrc;
+        impl Crc32 for MockCrc {
+            fn hash(&self, _data: &[u8]) -> u32 {
+                42
+            }
+        }
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let chunk = Chunk::new_with_crc(chunk_type, b"mocked".to_vec(), &MockCrc);
+        let bytes = Png::from_chunks(vec![chunk]).as_bytes();
+
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+        let png = Png::try_from_with_crc(bytes.as_ref(), &MockCrc).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().crc(), 42);
+    }
+
     #[test]
     fn test_list_chunks() {
         let png = testing_png();
@@ -227,6 +672,15 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_chunk_types() {
+        let mut png = testing_png();
+        assert_eq!(png.chunk_types(), vec!["FrSt", "miDl", "LASt"]);
+
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
+        assert_eq!(png.chunk_types(), vec!["FrSt", "miDl", "LASt", "TeSt"]);
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -235,19 +689,354 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_contains_chunk_type() {
+        let png = testing_png();
+        assert!(png.contains_chunk_type("miDl"));
+        assert!(!png.contains_chunk_type("NoNe"));
+    }
+
+    #[test]
+    fn test_find_chunk_index() {
+        let mut png = testing_png();
+        assert_eq!(png.find_chunk_index("miDl"), Some(1));
+        assert_eq!(png.find_chunk_index("NoNe"), None);
+        assert!(png.find_all_indices("NoNe").is_empty());
+
+        png.append_chunk(chunk_from_strings("FrSt", "I am a duplicate").unwrap()).unwrap();
+        assert_eq!(png.find_chunk_index("FrSt"), Some(0));
+        assert_eq!(png.find_all_indices("FrSt"), vec![0, 3]);
+    }
+
+    #[test]
+    fn test_shuffle_ancillary() {
+        let types = ["IHDR", "tEXt", "IDAT", "IDAT", "zTXt", "ruSt", "biNy", "IEND"];
+        let chunks = types
+            .iter()
+            .map(|chunk_type| chunk_from_strings(chunk_type, chunk_type).unwrap())
+            .collect();
+        let mut png = Png::from_chunks(chunks);
+        png.shuffle_ancillary(42);
+
+        let shuffled: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            shuffled,
+            vec!["IHDR", "tEXt", "IDAT", "IDAT", "zTXt", "biNy", "ruSt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_shuffle_keeps_chunks_before_idat() {
+        let types = ["IHDR", "gAMA", "pHYs", "IDAT", "tEXt", "ruSt", "IEND"];
+        let chunks = types
+            .iter()
+            .map(|chunk_type| chunk_from_strings(chunk_type, chunk_type).unwrap())
+            .collect();
+        let mut png = Png::from_chunks(chunks);
+        for seed in 1..20 {
+            png.shuffle_ancillary(seed);
+            let idat = png.find_chunk_index("IDAT").unwrap();
+            assert_eq!(idat, 3);
+            assert!(png.find_chunk_index("gAMA").unwrap() < idat);
+            assert!(png.find_chunk_index("pHYs").unwrap() < idat);
+            assert!(png.find_chunk_index("tEXt").unwrap() > idat);
+        }
+    }
+
+    #[test]
+    fn test_swap_chunks() {
+        let mut png = testing_png();
+        png.swap_chunks(0, 2).unwrap();
+        assert_eq!(png.chunk_types(), vec!["LASt", "miDl", "FrSt"]);
+        assert!(png.swap_chunks(1, 3).is_err());
+
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("ruSt", "message").unwrap(),
+            chunk_from_strings("teSt", "another").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert!(png.swap_chunks(0, 1).is_err());
+        assert!(png.swap_chunks(2, 3).is_err());
+        png.swap_chunks(1, 2).unwrap();
+        assert_eq!(png.chunk_types(), vec!["IHDR", "teSt", "ruSt", "IEND"]);
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
-        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
         let chunk = png.chunk_by_type("TeSt").unwrap();
         assert_eq!(&chunk.chunk_type().to_string(), "TeSt");
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_push_raw_chunk() {
+        let mut png = testing_png();
+        let raw = chunk_from_strings("TeSt", "Raw message").unwrap().as_bytes();
+        png.push_raw_chunk(&raw[..]).unwrap();
+
+        let chunk = png.chunk_by_type("TeSt").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "Raw message");
+        assert_eq!(chunk.as_bytes(), raw);
+
+        assert!(png.push_raw_chunk(&raw[..raw.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        png.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+
+        assert_eq!(png.chunks().len(), 2);
+        assert!(png.chunk_by_type("miDl").is_none());
+    }
+
+    #[test]
+    fn test_truncated_and_short_files_are_errors() {
+        let bytes = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            Chunk::new(ChunkType::from_str("ruSt").unwrap(), vec![b'x'; 20]),
+        ])
+        .as_bytes();
+        let mut truncated = bytes.clone();
+        let length_at = bytes.len() - 32;
+        truncated[length_at..length_at + 4].copy_from_slice(&1000u32.to_be_bytes());
+        assert_eq!(
+            Png::try_from(&truncated[..]).unwrap_err(),
+            "chunk length exceeds available data"
+        );
+
+        assert!(Png::try_from(&bytes[..5]).is_err());
+        assert!(Png::try_from(&bytes[..8 + 7]).is_err());
+
+        let mut bad_type = bytes.clone();
+        bad_type[length_at + 4..length_at + 8].copy_from_slice(&[0xff; 4]);
+        assert!(Png::try_from_ignoring_crc(&bad_type[..], ParseLimits::default()).is_err());
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("FrSt", "I am a duplicate").unwrap()).unwrap();
+
+        let chunks = png.chunks_by_type("FrSt");
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[0].data(), b"I am the first chunk");
+        assert_eq!(chunks[1].data(), b"I am a duplicate");
+        assert!(png.chunks_by_type("nOne").is_empty());
+    }
+
+    #[test]
+    fn test_remove_matching() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("teXt", "one").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+            chunk_from_strings("ruSt", "two").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        let removed = png.remove_matching("*t");
+
+        let removed: Vec<String> = removed.iter().map(|chunk| chunk.chunk_type().to_string()).collect();
+        assert_eq!(removed, vec!["teXt", "ruSt"]);
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IDAT", "IEND"]);
+        assert!(png.remove_matching("I*").is_empty());
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_map_chunks() {
+        let mut png = testing_png();
+        png.map_chunks(|_, data| data.make_ascii_uppercase());
+
+        let chunk = png.chunk_by_type("miDl").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I AM ANOTHER CHUNK");
+        assert!(png.chunks().iter().all(|chunk| chunk.crc_matches()));
+        assert!(Png::try_from(png.as_bytes().as_ref()).is_ok());
+    }
+
+    #[test]
+    fn test_recompute_crcs() {
+        use crate::chunk::compute_crc;
+
+        let mut stale = chunk_from_strings("ruSt", "original").unwrap();
+        stale.data_mut().extend_from_slice(b" and edited");
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            stale,
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert!(Png::try_from(&png.as_bytes()[..]).is_err());
+
+        png.recompute_crcs();
+        let path = std::env::temp_dir().join(format!("ping_pong_{}_recompute.png", std::process::id()));
+        fs::write(&path, png.as_bytes()).unwrap();
+        let saved = Png::from_file(&path).unwrap();
+        let chunk = saved.chunk_by_type("ruSt").unwrap();
+        assert_eq!(chunk.data(), b"original and edited");
+        assert_eq!(chunk.crc(), compute_crc(chunk.chunk_type(), chunk.data()));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_reassemble() {
+        use std::str::FromStr;
+
+        let mut png = testing_png();
+        let parts = [("reAa", 2u32, "!"), ("reBb", 0, "Hello"), ("reCc", 1, ", world")];
+        for (chunk_type, index, part) in parts.iter() {
+            let data = index
+                .to_be_bytes()
+                .iter()
+                .chain(part.as_bytes())
+                .copied()
+                .collect();
+            png.append_chunk(Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)).unwrap();
+        }
+
+        assert_eq!(png.reassemble("re").unwrap(), b"Hello, world!".to_vec());
+        assert!(png.reassemble("zz").is_err());
+    }
+
+    #[test]
+    fn test_order_signal_round_trip() {
+        use std::str::FromStr;
+
+        let value = 0b1011_0010u8;
+        let bits: Vec<bool> = (0..8).rev().map(|shift| value >> shift & 1 == 1).collect();
+        let mut png = testing_png();
+        png.embed_order_signal(&ChunkType::from_str("orDr").unwrap(), &bits).unwrap();
+
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        let recovered = png.read_order_signal("orDr").unwrap();
+        let byte = recovered.iter().fold(0u8, |acc, &bit| acc << 1 | bit as u8);
+        assert_eq!(png.find_all_indices("orDr").len(), 16);
+        assert_eq!(byte, value);
+        assert!(png.read_order_signal("nOne").is_err());
+    }
+
+    #[test]
+    fn test_clone_with_chunk() {
+        let base = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        let png = base.clone_with_chunk(chunk_from_strings("ruSt", "message").unwrap()).unwrap();
+
+        assert_eq!(base.chunk_types(), vec!["IHDR", "IEND"]);
+        assert_eq!(png.chunk_types(), vec!["IHDR", "ruSt", "IEND"]);
+        assert!(base.clone_with_chunk(chunk_from_strings("IEND", "").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_append_chunk_without_iend() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+        ]);
+        assert!(!png.ends_with_iend());
+
+        png.append_chunk(chunk_from_strings("ruSt", "message").unwrap()).unwrap();
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IDAT", "ruSt"]);
+
+        let mut empty = Png::new_empty();
+        assert!(!empty.ends_with_iend());
+        empty.append_chunk(chunk_from_strings("ruSt", "message").unwrap()).unwrap();
+        assert_eq!(empty.chunk_types(), vec!["ruSt"]);
+    }
+
+    #[test]
+    fn test_append_chunk_refuses_ihdr_and_iend() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        assert!(png.append_chunk(chunk_from_strings("IHDR", "again").unwrap()).is_err());
+        assert!(png.append_chunk(chunk_from_strings("IEND", "").unwrap()).is_err());
+        assert!(png.extend_chunks(vec![chunk_from_strings("IEND", "").unwrap()]).is_err());
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IEND"]);
+
+        png.force_append_chunk(chunk_from_strings("IHDR", "again").unwrap());
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IHDR", "IEND"]);
+    }
+
+    #[test]
+    fn test_merge_idat() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "first ").unwrap(),
+            chunk_from_strings("IDAT", "second ").unwrap(),
+            chunk_from_strings("tEXt", "between").unwrap(),
+            chunk_from_strings("IDAT", "third").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.merge_idat().unwrap();
+
+        assert_eq!(png.chunk_types(), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+        let idat = png.chunk_by_type("IDAT").unwrap();
+        assert_eq!(idat.data(), b"first second third");
+        assert!(idat.crc_matches());
+        assert!(Png::try_from(&png.as_bytes()[..]).is_ok());
+        assert!(testing_png().merge_idat().is_err());
+    }
+
+    #[test]
+    fn test_insert_text() {
+        let mut png = testing_png();
+        png.insert_text("Comment", "caf\u{e9}").unwrap();
+        let chunk = png.chunk_by_type("tEXt").unwrap();
+        assert_eq!(chunk.data(), b"Comment\0caf\xe9");
+        assert!(chunk.crc_matches());
+
+        let long_keyword = "k".repeat(80);
+        assert!(png.insert_text(&long_keyword, "text").is_err());
+        assert!(png.insert_text(&long_keyword[..79], "text").is_ok());
+        assert!(png.insert_text("", "text").is_err());
+        assert!(png.insert_text("Titl\u{e9}\u{263a}", "text").is_err());
+        assert!(png.insert_text("Title", "\u{263a}").is_err());
+        assert_eq!(png.find_all_indices("tEXt").len(), 2);
+    }
+
+    #[test]
+    fn test_extend_chunks() {
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.extend_chunks(vec![
+            chunk_from_strings("onEe", "1").unwrap(),
+            chunk_from_strings("twOo", "2").unwrap(),
+            chunk_from_strings("thRe", "3").unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(png.chunk_types(), vec!["IHDR", "onEe", "twOo", "thRe", "IEND"]);
+
+        assert!(png.extend_chunks(vec![chunk_from_strings("bads", "x").unwrap()]).is_err());
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_append_to_chunk() {
+        let mut png = testing_png();
+        png.append_to_chunk("miDl", b", with more").unwrap();
+
+        let chunk = png.chunk_by_type("miDl").unwrap();
+        let expected = chunk_from_strings("miDl", "I am another chunk, with more").unwrap();
+        assert_eq!(&chunk.data_as_string().unwrap(), "I am another chunk, with more");
+        assert_eq!(chunk.crc(), expected.crc());
+        assert!(png.append_to_chunk("NoNe", b"extra").is_err());
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
-        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap());
+        png.append_chunk(chunk_from_strings("TeSt", "Message").unwrap()).unwrap();
         png.remove_chunk("TeSt").unwrap();
         let chunk = png.chunk_by_type("TeSt");
         assert!(chunk.is_none());
@@ -267,6 +1056,42 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_byte_len() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.byte_len(), PNG_FILE.len());
+    }
+
+    #[test]
+    fn test_chunk_offset() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let index = png.find_chunk_index("IDAT").unwrap();
+        let type_position = PNG_FILE
+            .windows(4)
+            .position(|window| window == b"IDAT")
+            .unwrap();
+
+        assert_eq!(png.chunk_offset(index), Some(type_position - 4));
+        assert_eq!(png.chunk_offset(0), Some(8));
+        assert_eq!(png.chunk_offset(png.chunks().len()), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        let dir = std::env::temp_dir();
+        let png_path = dir.join(format!("ping_pong_{}_sniff.png", std::process::id()));
+        let jpeg_path = dir.join(format!("ping_pong_{}_sniff.jpg", std::process::id()));
+        // only the signature is needed, the rest is never read
+        fs::write(&png_path, &PNG_FILE[..8]).unwrap();
+        fs::write(&jpeg_path, [0xff, 0xd8, 0xff, 0xe0, 0, 16, b'J', b'F', b'I', b'F']).unwrap();
+
+        assert!(Png::sniff(&png_path));
+        assert!(!Png::sniff(&jpeg_path));
+        assert!(!Png::sniff(dir.join("ping_pong_no_such_file.png")));
+        fs::remove_file(png_path).unwrap();
+        fs::remove_file(jpeg_path).unwrap();
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
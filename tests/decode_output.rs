@@ -0,0 +1,33 @@
+use ping_pong::chunk::Chunk;
+use ping_pong::chunk_type::ChunkType;
+use ping_pong::png::Png;
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::str::FromStr;
+
+fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec())
+}
+
+#[test]
+fn decode_prints_only_the_message() {
+    let path = env::temp_dir().join(format!("ping_pong_{}_decode_output.png", std::process::id()));
+    let png = Png::from_chunks(vec![
+        chunk("IHDR", b"header"),
+        chunk("IDAT", b"data"),
+        chunk("ruSt", b"hidden message"),
+        chunk("IEND", b""),
+    ]);
+    fs::write(&path, png.as_bytes()).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ping_pong"))
+        .args(["decode", "-c", "ruSt", "-f"])
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "hidden message\n");
+}